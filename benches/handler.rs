@@ -2,6 +2,7 @@
 
 use criterion::{Criterion, black_box, criterion_group, criterion_main};
 use marky::handler::Handle;
+use tower_lsp::lsp_types;
 
 fn benchmark_handler_new(c: &mut Criterion) {
     let code = [
@@ -72,14 +73,62 @@ fn benchmark_handler_update_no_change(c: &mut Criterion) {
 
     c.bench_function("handler.update.no_change", |b| {
         let mut handler = Handle::new(&code, &mut parser).expect("Failed to create handle");
+        let changes = [lsp_types::TextDocumentContentChangeEvent {
+            range: None,
+            range_length: None,
+            text: code.clone(),
+        }];
 
+        let mut version = 0;
         b.iter(|| {
             let handle = black_box(&mut handler);
-            let code = black_box(&code);
+            let changes = black_box(&changes);
             let parser = black_box(&mut parser);
 
+            version += 1;
             handle
-                .update(code, parser)
+                .update(version, changes, parser)
+                .expect("Failed to update handle");
+        })
+    });
+}
+
+fn benchmark_handler_update_incremental(c: &mut Criterion) {
+    let paragraph = "Lorem ipsum dolor sit amet, consectetur adipiscing elit. Sed do eiusmod tempor incididunt ut labore et dolore magna aliqua.";
+    let code = (0..1000)
+        .map(|index| format!("## Section {}\n\n{}\n", index, paragraph))
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    let mut parser = tree_sitter::Parser::new();
+    parser
+        .set_language(tree_sitter_md::language())
+        .expect("Failed to set language");
+
+    c.bench_function("handler.update.incremental", |b| {
+        let mut handler = Handle::new(&code, &mut parser).expect("Failed to create handle");
+        let position = lsp_types::Position {
+            line: 2000,
+            character: 0,
+        };
+        let changes = [lsp_types::TextDocumentContentChangeEvent {
+            range: Some(lsp_types::Range {
+                start: position,
+                end: position,
+            }),
+            range_length: None,
+            text: "x".to_string(),
+        }];
+
+        let mut version = 0;
+        b.iter(|| {
+            let handle = black_box(&mut handler);
+            let changes = black_box(&changes);
+            let parser = black_box(&mut parser);
+
+            version += 1;
+            handle
+                .update(version, changes, parser)
                 .expect("Failed to update handle");
         })
     });
@@ -88,6 +137,7 @@ fn benchmark_handler_update_no_change(c: &mut Criterion) {
 criterion_group!(
     benches,
     benchmark_handler_new,
-    benchmark_handler_update_no_change
+    benchmark_handler_update_no_change,
+    benchmark_handler_update_incremental
 );
 criterion_main!(benches);
@@ -0,0 +1,185 @@
+use std::collections::HashMap;
+
+use tower_lsp::lsp_types;
+
+use crate::hover;
+use crate::llm::Llm;
+
+/// LLM powered rewrites offered for the block under the cursor.
+#[derive(Debug, Clone, Copy, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum Rewrite {
+    Summarize,
+    Clarify,
+    Concise,
+    Grammar,
+}
+
+impl Rewrite {
+    const ALL: [Self; 4] = [Self::Summarize, Self::Clarify, Self::Concise, Self::Grammar];
+
+    fn title(self) -> &'static str {
+        match self {
+            Self::Summarize => "Summarize this section",
+            Self::Clarify => "Rewrite for clarity",
+            Self::Concise => "Make more concise",
+            Self::Grammar => "Fix grammar",
+        }
+    }
+
+    fn instruction(self) -> &'static str {
+        match self {
+            Self::Summarize => {
+                "Summarize the following Markdown text. Reply with the summary only, as Markdown."
+            }
+            Self::Clarify => {
+                "Rewrite the following Markdown text so it is clearer to read, keeping its meaning, tone and formatting. Reply with the rewritten text only."
+            }
+            Self::Concise => {
+                "Rewrite the following Markdown text to be more concise without losing information, keeping its formatting. Reply with the rewritten text only."
+            }
+            Self::Grammar => {
+                "Fix the spelling, grammar and punctuation of the following Markdown text without changing its wording otherwise. Reply with the corrected text only."
+            }
+        }
+    }
+}
+
+/// Carried from `textDocument/codeAction` to `codeAction/resolve`, the LLM only runs once the
+/// user picked an action.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+struct Data {
+    uri: lsp_types::Url,
+    position: lsp_types::Position,
+    rewrite: Rewrite,
+    /// Hash of the block when the action was offered, to detect edits in between.
+    hash: String,
+}
+
+pub async fn code_action(
+    ctx: &crate::Backend,
+    params: lsp_types::CodeActionParams,
+) -> anyhow::Result<Option<lsp_types::CodeActionResponse>> {
+    let uri = params.text_document.uri;
+    let position = params.range.start;
+
+    let Some(handle) = ctx.project.documents.get(&uri).await else {
+        return Ok(None);
+    };
+
+    hover::select(ctx, &uri, params.range);
+
+    let mut actions = Vec::new();
+    if params.range.start != params.range.end {
+        actions.push(lsp_types::CodeActionOrCommand::Command(
+            hover::summarize_command(&uri, params.range),
+        ));
+    }
+
+    let (row, col) = handle.point(position);
+    let Some(block) = handle.get_block(row, col) else {
+        return Ok(Some(actions));
+    };
+
+    // Rewriting code or markup as prose would break it
+    if !block.kind.is_prose() {
+        return Ok(Some(actions));
+    }
+
+    let hash = blake3::Hash::from(block.metadata.hash).to_hex().to_string();
+
+    let resolves = resolves_edits(ctx);
+
+    for rewrite in Rewrite::ALL {
+        let data = Data {
+            uri: uri.clone(),
+            position,
+            rewrite,
+            hash: hash.clone(),
+        };
+        let action = lsp_types::CodeAction {
+            title: rewrite.title().to_string(),
+            kind: Some(lsp_types::CodeActionKind::REFACTOR_REWRITE),
+            data: Some(serde_json::to_value(data)?),
+            ..Default::default()
+        };
+
+        // Clients which can not resolve the edit lazily get it right away, an action whose
+        // edit could not be made would do nothing
+        let action = match resolves {
+            true => action,
+            false => match resolve(ctx, action).await {
+                Ok(action) => action,
+                Err(_) => continue,
+            },
+        };
+        actions.push(lsp_types::CodeActionOrCommand::CodeAction(action));
+    }
+
+    Ok(Some(actions))
+}
+
+/// Whether the client supports `codeAction/resolve` for the edit of an action.
+fn resolves_edits(ctx: &crate::Backend) -> bool {
+    ctx.capabilities
+        .get()
+        .and_then(|capabilities| capabilities.text_document.as_ref())
+        .and_then(|text_document| text_document.code_action.as_ref())
+        .and_then(|code_action| code_action.resolve_support.as_ref())
+        .is_some_and(|support| support.properties.iter().any(|property| property == "edit"))
+}
+
+pub async fn resolve(
+    ctx: &crate::Backend,
+    mut action: lsp_types::CodeAction,
+) -> anyhow::Result<lsp_types::CodeAction> {
+    let Some(data) = action.data.clone() else {
+        return Ok(action);
+    };
+    let data: Data = serde_json::from_value(data)?;
+
+    let handle = ctx
+        .project
+        .documents
+        .get(&data.uri)
+        .await
+        .ok_or_else(|| anyhow::anyhow!("Document is no longer open: {}", data.uri))?;
+
+    let (row, col) = handle.point(data.position);
+    let block = handle
+        .get_block(row, col)
+        .filter(|block| blake3::Hash::from(block.metadata.hash).to_hex().as_str() == data.hash)
+        .ok_or_else(|| anyhow::anyhow!("The block changed since the action was offered"))?;
+
+    let range = handle.range(block.start, block.end);
+    let original = handle
+        .text_in(range)
+        .ok_or_else(|| anyhow::anyhow!("Failed while reading the block"))?;
+
+    let generated = ctx
+        .artifacts
+        .read()
+        .await
+        .llm()?
+        .generate(data.rewrite.instruction(), &block.text)
+        .await?;
+
+    // The node span includes surrounding whitespace (e.g. the line break), keep it intact.
+    let leading = original
+        .get(..original.len() - original.trim_start().len())
+        .unwrap_or_default();
+    let trailing = original
+        .get(original.trim_end().len()..)
+        .unwrap_or_default();
+    let new_text = format!("{}{}{}", leading, generated.trim(), trailing);
+
+    action.edit = Some(lsp_types::WorkspaceEdit {
+        changes: Some(HashMap::from([(
+            data.uri,
+            vec![lsp_types::TextEdit { range, new_text }],
+        )])),
+        ..Default::default()
+    });
+
+    Ok(action)
+}
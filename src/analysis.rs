@@ -0,0 +1,294 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use tokio::sync::Semaphore;
+use tokio::task;
+use tower_lsp::lsp_types;
+
+use crate::handler::{
+    self,
+    registry::{self, Load},
+};
+use crate::progress::Progress;
+
+/// How often the model work is reported while in progress.
+const REPORT_INTERVAL: Duration = Duration::from_millis(500);
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct Config {
+    /// Quiet time after the last change before a document is analyzed.
+    pub debounce_ms: u64,
+    /// Documents analyzed at the same time.
+    pub concurrency: usize,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            debounce_ms: 500,
+            concurrency: 2,
+        }
+    }
+}
+
+struct Job {
+    /// Tells the job apart from the ones scheduled for the same document before and after it.
+    id: u64,
+    task: task::JoinHandle<()>,
+}
+
+/// Runs keyword extraction, summarization and embedding for documents in the background.
+///
+/// Analysis starts once a document has been left alone for the debounce time, a newer version of
+/// the document cancels the job of the previous one along with its queued model work, and only a
+/// bounded number of documents are analyzed at once.
+#[derive(Clone)]
+pub struct Scheduler {
+    jobs: Arc<Mutex<HashMap<lsp_types::Url, Job>>>,
+    next_id: Arc<AtomicU64>,
+    permits: Arc<Semaphore>,
+    /// Permits the semaphore was configured with, whether or not they are held.
+    concurrency: Arc<AtomicUsize>,
+    debounce: Arc<AtomicU64>,
+    /// Whether the model work is being reported as progress.
+    reporting: Arc<AtomicBool>,
+}
+
+impl Scheduler {
+    pub fn new() -> Self {
+        let config = Config::default();
+
+        Self {
+            jobs: Arc::new(Mutex::new(HashMap::new())),
+            next_id: Arc::new(AtomicU64::new(0)),
+            permits: Arc::new(Semaphore::new(config.concurrency)),
+            concurrency: Arc::new(AtomicUsize::new(config.concurrency)),
+            debounce: Arc::new(AtomicU64::new(config.debounce_ms)),
+            reporting: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    /// Applies the configuration, the number of permits is adjusted from the one last configured.
+    pub fn configure(&self, config: &Config) {
+        self.debounce.store(config.debounce_ms, Ordering::Relaxed);
+
+        let concurrency = config.concurrency.max(1);
+        let current = self.concurrency.swap(concurrency, Ordering::AcqRel);
+        if concurrency > current {
+            self.permits.add_permits(concurrency - current);
+            return;
+        }
+
+        // Permits held by running jobs are only forgotten once those jobs release them
+        let excess = current - concurrency;
+        let held = excess - self.permits.forget_permits(excess);
+        if held > 0 {
+            let permits = self.permits.clone();
+            let held = u32::try_from(held).unwrap_or(u32::MAX);
+            task::spawn(async move {
+                if let Ok(permit) = permits.acquire_many_owned(held).await {
+                    permit.forget();
+                }
+            });
+        }
+    }
+
+    /// Schedules the analysis of a document, `cursor` is where it was last edited and the block
+    /// there is analyzed first.
+    pub fn schedule(
+        &self,
+        ctx: &crate::Backend,
+        uri: lsp_types::Url,
+        blocks: Vec<handler::Block>,
+        cursor: Option<(usize, usize)>,
+    ) {
+        let backend = ctx.clone();
+        let permits = self.permits.clone();
+        let debounce = Duration::from_millis(self.debounce.load(Ordering::Relaxed));
+        let reporting = self.reporting.clone();
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let finished = (self.jobs.clone(), uri.clone());
+
+        // Held until the job is in the map, so a job finishing right away still finds itself
+        let Ok(mut jobs) = self.jobs.lock() else {
+            return;
+        };
+
+        let task = task::spawn(async move {
+            tokio::time::sleep(debounce).await;
+
+            let Ok(_permit) = permits.acquire_owned().await else {
+                return;
+            };
+
+            // Reported on its own, the job may be superseded while the models still work
+            task::spawn(report(backend.clone(), reporting));
+
+            analyze(&backend, blocks, cursor).await;
+
+            let (jobs, uri) = finished;
+            if let Ok(mut jobs) = jobs.lock()
+                && jobs.get(&uri).is_some_and(|job| job.id == id)
+            {
+                jobs.remove(&uri);
+            }
+        });
+
+        if let Some(previous) = jobs.insert(uri, Job { id, task }) {
+            previous.task.abort();
+        }
+    }
+
+    /// Drops the pending job of a document, e.g. once it is closed.
+    pub fn cancel(&self, uri: &lsp_types::Url) {
+        if let Ok(mut jobs) = self.jobs.lock()
+            && let Some(job) = jobs.remove(uri)
+        {
+            job.task.abort();
+        }
+    }
+}
+
+impl Default for Scheduler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+async fn analyze(
+    ctx: &crate::Backend,
+    blocks: Vec<handler::Block>,
+    cursor: Option<(usize, usize)>,
+) {
+    let registry = &ctx.project.registry;
+
+    let (urgent, rest): (Vec<_>, Vec<_>) = blocks.into_iter().partition(|block| {
+        cursor.is_some_and(|cursor| block.start <= cursor && cursor <= block.end)
+    });
+
+    // Only queues work, the models run on the registry's own worker thread
+    for blocks in [&urgent, &rest] {
+        let _ = registry.keyword_text(blocks);
+        let _ = registry.summarize_text(blocks);
+    }
+    for block in &urgent {
+        let _ = registry.prioritize(&block.metadata.hash);
+    }
+
+    if let Ok(en) = ctx.artifacts.read().await.embedding() {
+        let _ = registry.embed_text(&urgent, en).await;
+        let _ = registry.embed_text(&rest, en).await;
+    }
+}
+
+/// Reports the queued model work as `$/progress` until the queue drains, one report at a time.
+/// Work done before the first report never shows up.
+async fn report(ctx: crate::Backend, reporting: Arc<AtomicBool>) {
+    if reporting.swap(true, Ordering::AcqRel) {
+        return;
+    }
+
+    let mut progress = None;
+    let mut most = 0;
+
+    loop {
+        tokio::time::sleep(REPORT_INTERVAL).await;
+        log_errors(&ctx).await;
+
+        let Ok(status) = ctx.project.registry.status() else {
+            break;
+        };
+        let remaining = status.queue.total();
+        if remaining == 0 {
+            break;
+        }
+        most = usize::max(most, remaining);
+
+        let progress = match &mut progress {
+            Some(progress) => progress,
+            None => progress.insert(Progress::begin(&ctx, "Analyzing documents").await),
+        };
+
+        #[allow(clippy::as_conversions)]
+        let percentage = ((most - remaining) * 100 / most) as u32;
+        progress.report(message(&status), percentage).await;
+    }
+
+    if let Some(progress) = progress {
+        progress.end("Analysis complete").await;
+    }
+    log_errors(&ctx).await;
+
+    reporting.store(false, Ordering::Release);
+}
+
+async fn log_errors(ctx: &crate::Backend) {
+    for e in ctx.project.registry.take_errors() {
+        warn!(ctx, "{}", e);
+    }
+}
+
+fn message(status: &registry::Status) -> String {
+    let mut message = format!(
+        "{} keywords, {} summaries, {} embeddings queued",
+        status.queue.keywords, status.queue.summaries, status.queue.embeddings
+    );
+
+    for (model, load) in [
+        ("keyword", status.models.keywords),
+        ("summarization", status.models.summarization),
+    ] {
+        if load == Load::Loading {
+            message.push_str(&format!(", loading the {} model", model));
+        }
+    }
+
+    message
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::ensure;
+
+    #[tokio::test]
+    async fn test_configure_permits() -> anyhow::Result<()> {
+        let scheduler = super::Scheduler::new();
+        let config = |concurrency| super::Config {
+            concurrency,
+            ..Default::default()
+        };
+
+        scheduler.configure(&config(4));
+        ensure!(scheduler.permits.available_permits() == 4);
+
+        // A held permit is forgotten once it is released
+        let held = scheduler.permits.clone().acquire_owned().await?;
+        scheduler.configure(&config(1));
+        ensure!(scheduler.permits.available_permits() == 0);
+        drop(held);
+        tokio::task::yield_now().await;
+        ensure!(scheduler.permits.available_permits() == 1);
+
+        scheduler.configure(&config(3));
+        ensure!(scheduler.permits.available_permits() == 3);
+
+        // Jobs started after lowering the concurrency under load are held to the new limit
+        let permits = scheduler.permits.clone();
+        let running = [
+            permits.clone().acquire_owned().await?,
+            permits.clone().acquire_owned().await?,
+            permits.clone().acquire_owned().await?,
+        ];
+        scheduler.configure(&config(1));
+        drop(running);
+        tokio::task::yield_now().await;
+
+        let _running = permits.clone().try_acquire_owned()?;
+        ensure!(permits.clone().try_acquire_owned().is_err());
+
+        Ok(())
+    }
+}
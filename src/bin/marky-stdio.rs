@@ -5,7 +5,11 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     let stdin = tokio::io::stdin();
     let stdout = tokio::io::stdout();
 
-    let (service, socket) = LspService::new(marky::Backend::new);
+    let (service, socket) = LspService::build(marky::Backend::new)
+        .custom_method("marky/hoverRange", marky::Backend::hover_range)
+        .custom_method("marky/search", marky::Backend::search)
+        .custom_method("marky/status", marky::Backend::status)
+        .finish();
 
     Server::new(stdin, stdout, socket).serve(service).await;
 
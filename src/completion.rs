@@ -0,0 +1,508 @@
+use std::collections::BTreeSet;
+
+use tower_lsp::lsp_types;
+
+use crate::handler::{frontmatter, link};
+
+#[cfg(feature = "intelligence")]
+use crate::embedding::Embedding;
+
+/// Number of semantically related links offered after `[[`.
+#[cfg(feature = "intelligence")]
+const RELATED_LIMIT: usize = 10;
+
+/// The paragraph related links were completed for, by the hash of its text, and its embedding.
+#[cfg(feature = "intelligence")]
+pub struct Query {
+    hash: [u8; 32],
+    embedding: Vec<f32>,
+}
+
+/// What is being typed, judging from the text between the start of the line and the cursor.
+#[derive(Debug, PartialEq, Eq)]
+enum Context<'a> {
+    /// `[text](path`
+    Path(&'a str),
+    /// `[text](path#anchor`
+    Anchor { path: &'a str, anchor: &'a str },
+    /// `[text][label`
+    Reference(&'a str),
+    /// `[^label`
+    Footnote(&'a str),
+    /// `[[text`, a link to related content
+    Related(&'a str),
+}
+
+impl<'a> Context<'a> {
+    fn parse(prefix: &'a str) -> Option<Self> {
+        let opener = ["](", "][", "[^", "[["]
+            .into_iter()
+            .filter_map(|opener| prefix.rfind(opener).map(|index| (index, opener)))
+            .max_by_key(|(index, _)| *index);
+
+        let (index, opener) = opener?;
+        let typed = prefix.get(index + opener.len()..)?;
+
+        match opener {
+            "](" => {
+                let typed = typed.strip_prefix('<').unwrap_or(typed);
+                if typed.contains([')', '>']) || typed.contains(char::is_whitespace) {
+                    return None;
+                }
+
+                Some(match typed.split_once('#') {
+                    Some((path, anchor)) => Self::Anchor { path, anchor },
+                    None => Self::Path(typed),
+                })
+            }
+            _ if typed.contains(']') => None,
+            "][" => Some(Self::Reference(typed)),
+            "[[" => Some(Self::Related(typed)),
+            _ => Some(Self::Footnote(typed)),
+        }
+    }
+}
+
+/// Completes link destinations (paths relative to the document and heading anchors), reference
+/// labels and footnote labels.
+pub async fn completion(
+    ctx: &crate::Backend,
+    params: lsp_types::CompletionParams,
+) -> anyhow::Result<Option<lsp_types::CompletionResponse>> {
+    let uri = params.text_document_position.text_document.uri;
+    let position = params.text_document_position.position;
+
+    let Some(handle) = ctx.project.handle(&uri).await else {
+        return Ok(None);
+    };
+
+    let Some(prefix) = handle.text_in(lsp_types::Range {
+        start: lsp_types::Position {
+            line: position.line,
+            character: 0,
+        },
+        end: position,
+    }) else {
+        return Ok(None);
+    };
+
+    let (row, _) = handle.point(position);
+    let frontmatter = handle
+        .frontmatter
+        .read()
+        .map_err(|_| anyhow::anyhow!("Failed while reading the frontmatter"))?
+        .clone()
+        .filter(|frontmatter| frontmatter.contains(row));
+    if let Some(frontmatter) = frontmatter {
+        return Ok(Some(lsp_types::CompletionResponse::Array(keys(
+            ctx,
+            &frontmatter,
+            &prefix,
+            position,
+        ))));
+    }
+
+    let Some(context) = Context::parse(&prefix) else {
+        return Ok(None);
+    };
+
+    let items = match context {
+        Context::Path(path) => paths(&uri, path, position).await?,
+        Context::Anchor { path, anchor } => anchors(ctx, &uri, path, anchor, position).await?,
+        Context::Reference(label) => labels(&handle, link::Kind::Definition, label, position)?,
+        Context::Footnote(label) => {
+            labels(&handle, link::Kind::FootnoteDefinition, label, position)?
+        }
+        Context::Related(typed) => {
+            let items = related(ctx, &uri, &handle, typed, position).await?;
+
+            // The ranking depends on the paragraph rather than on what is typed, the client
+            // filters the list as the title is typed
+            return Ok(Some(lsp_types::CompletionResponse::List(
+                lsp_types::CompletionList {
+                    is_incomplete: false,
+                    items,
+                },
+            )));
+        }
+    };
+
+    Ok(Some(lsp_types::CompletionResponse::Array(items)))
+}
+
+/// Entries of the directory being typed, relative to the document.
+async fn paths(
+    uri: &lsp_types::Url,
+    path: &str,
+    position: lsp_types::Position,
+) -> anyhow::Result<Vec<lsp_types::CompletionItem>> {
+    let (directory, segment) = match path.rsplit_once('/') {
+        Some((directory, segment)) => (format!("{}/", directory), segment),
+        None => ("./".to_string(), path),
+    };
+
+    let Some(directory) =
+        link::resolve(uri, &directory).and_then(|directory| directory.to_file_path().ok())
+    else {
+        return Ok(vec![]);
+    };
+
+    let Ok(mut entries) = tokio::fs::read_dir(directory).await else {
+        return Ok(vec![]);
+    };
+
+    let range = replace(position, segment);
+    let mut items = Vec::new();
+
+    while let Some(entry) = entries.next_entry().await? {
+        let name = entry.file_name().to_string_lossy().to_string();
+        if name.starts_with('.') {
+            continue;
+        }
+
+        let (label, kind) = match entry.file_type().await?.is_dir() {
+            true => (format!("{}/", name), lsp_types::CompletionItemKind::FOLDER),
+            false => (name, lsp_types::CompletionItemKind::FILE),
+        };
+
+        items.push(lsp_types::CompletionItem {
+            label: label.clone(),
+            kind: Some(kind),
+            text_edit: Some(lsp_types::CompletionTextEdit::Edit(lsp_types::TextEdit {
+                range,
+                new_text: label,
+            })),
+            ..Default::default()
+        });
+    }
+
+    Ok(items)
+}
+
+/// Heading anchors of the target document, or of the document itself for `#anchor` links.
+async fn anchors(
+    ctx: &crate::Backend,
+    uri: &lsp_types::Url,
+    path: &str,
+    anchor: &str,
+    position: lsp_types::Position,
+) -> anyhow::Result<Vec<lsp_types::CompletionItem>> {
+    let Some(target) = link::resolve(uri, path) else {
+        return Ok(vec![]);
+    };
+    let Some(handle) = ctx.project.handle(&target).await else {
+        return Ok(vec![]);
+    };
+
+    let range = replace(position, anchor);
+    let headings = handle
+        .headings
+        .read()
+        .map_err(|_| anyhow::anyhow!("Failed while reading the headings"))?;
+
+    Ok(headings
+        .iter()
+        .enumerate()
+        .map(|(index, heading)| lsp_types::CompletionItem {
+            label: heading.slug.clone(),
+            kind: Some(lsp_types::CompletionItemKind::REFERENCE),
+            detail: Some(format!("{} {}", "#".repeat(heading.level), heading.text)),
+            // Keep the document order rather than the alphabetical one
+            sort_text: Some(format!("{:05}", index)),
+            filter_text: Some(heading.slug.clone()),
+            text_edit: Some(lsp_types::CompletionTextEdit::Edit(lsp_types::TextEdit {
+                range,
+                new_text: heading.slug.clone(),
+            })),
+            ..Default::default()
+        })
+        .collect())
+}
+
+/// Labels of the reference definitions or footnotes of the document.
+fn labels(
+    handle: &crate::handler::Handle,
+    kind: link::Kind,
+    typed: &str,
+    position: lsp_types::Position,
+) -> anyhow::Result<Vec<lsp_types::CompletionItem>> {
+    let links = handle
+        .links
+        .read()
+        .map_err(|_| anyhow::anyhow!("Failed while reading the links"))?;
+
+    let labels = links
+        .iter()
+        .filter(|link| link.kind == kind)
+        .filter_map(|link| link.label.clone())
+        .collect::<BTreeSet<_>>();
+
+    let range = replace(position, typed);
+
+    Ok(labels
+        .into_iter()
+        .map(|label| lsp_types::CompletionItem {
+            label: label.clone(),
+            kind: Some(lsp_types::CompletionItemKind::REFERENCE),
+            text_edit: Some(lsp_types::CompletionTextEdit::Edit(lsp_types::TextEdit {
+                range,
+                new_text: label,
+            })),
+            ..Default::default()
+        })
+        .collect())
+}
+
+/// Top level keys of the frontmatter schema missing from the document, or the allowed values of
+/// the key being written.
+fn keys(
+    ctx: &crate::Backend,
+    frontmatter: &frontmatter::Frontmatter,
+    prefix: &str,
+    position: lsp_types::Position,
+) -> Vec<lsp_types::CompletionItem> {
+    let Some(schema) = ctx.schema.get() else {
+        return vec![];
+    };
+
+    let separator = match frontmatter.format {
+        frontmatter::Format::Yaml => ':',
+        frontmatter::Format::Toml => '=',
+    };
+
+    // Nested keys and list items are not completed
+    if prefix.starts_with(char::is_whitespace) || prefix.starts_with('-') {
+        return vec![];
+    }
+
+    match prefix.split_once(separator) {
+        None => schema
+            .properties()
+            .into_iter()
+            .filter(|property| frontmatter.key(&property.name).is_none())
+            .map(|property| lsp_types::CompletionItem {
+                label: property.name.clone(),
+                kind: Some(lsp_types::CompletionItemKind::PROPERTY),
+                detail: Some(match property.required {
+                    true => format!("{} (required)", property.types.join(" | ")),
+                    false => property.types.join(" | "),
+                }),
+                documentation: property.description.map(lsp_types::Documentation::String),
+                // Required keys first
+                sort_text: Some(format!("{}{}", !property.required, property.name)),
+                text_edit: Some(lsp_types::CompletionTextEdit::Edit(lsp_types::TextEdit {
+                    range: replace(position, prefix),
+                    new_text: match frontmatter.format {
+                        frontmatter::Format::Yaml => format!("{}: ", property.name),
+                        frontmatter::Format::Toml => format!("{} = ", property.name),
+                    },
+                })),
+                ..Default::default()
+            })
+            .collect(),
+        Some((key, typed)) => {
+            let key = key.trim().trim_matches(['"', '\'']);
+            let typed = typed.trim_start();
+
+            let Some(property) = schema
+                .properties()
+                .into_iter()
+                .find(|property| property.name == key)
+            else {
+                return vec![];
+            };
+
+            property
+                .values
+                .iter()
+                .map(|value| {
+                    let new_text = match (value, frontmatter.format) {
+                        // Plain strings need no quotes in YAML
+                        (serde_json::Value::String(value), frontmatter::Format::Yaml) => {
+                            value.clone()
+                        }
+                        (value, _) => value.to_string(),
+                    };
+
+                    lsp_types::CompletionItem {
+                        label: new_text.clone(),
+                        kind: Some(lsp_types::CompletionItemKind::ENUM_MEMBER),
+                        text_edit: Some(lsp_types::CompletionTextEdit::Edit(lsp_types::TextEdit {
+                            range: replace(position, typed),
+                            new_text,
+                        })),
+                        ..Default::default()
+                    }
+                })
+                .collect()
+        }
+    }
+}
+
+/// Links to the headings (or blocks, outside of any section) of the workspace closest in
+/// meaning to the paragraph being written, replacing the `[[` and what follows it.
+#[cfg(feature = "intelligence")]
+async fn related(
+    ctx: &crate::Backend,
+    uri: &lsp_types::Url,
+    handle: &crate::handler::Handle,
+    typed: &str,
+    position: lsp_types::Position,
+) -> anyhow::Result<Vec<lsp_types::CompletionItem>> {
+    let (row, col) = handle.point(position);
+    let Some(block) = handle.get_block(row, col) else {
+        return Ok(vec![]);
+    };
+
+    // The paragraph is being edited, so its embedding is computed here, without the trigger and
+    // only once for as long as the rest of the paragraph stays the same
+    let trigger = format!("[[{}", typed);
+    let text = match block.text.rfind(&trigger) {
+        Some(index) => format!(
+            "{}{}",
+            block.text.get(..index).unwrap_or_default(),
+            block.text.get(index + trigger.len()..).unwrap_or_default()
+        ),
+        None => block.text.clone(),
+    };
+    let hash = *blake3::hash(text.as_bytes()).as_bytes();
+
+    let cached = ctx
+        .queries
+        .get(uri)
+        .filter(|query| query.hash == hash)
+        .map(|query| query.embedding.clone());
+    let query = match cached {
+        Some(query) => query,
+        None => {
+            let query = ctx.artifacts.read().await.embedding()?.embed(text).await?;
+            ctx.queries.insert(
+                uri.clone(),
+                Query {
+                    hash,
+                    embedding: query.clone(),
+                },
+            );
+            query
+        }
+    };
+
+    let hits = ctx
+        .project
+        .registry
+        .nearest(&query, RELATED_LIMIT * 2)
+        .await?;
+    let range = replace(position, &trigger);
+
+    let mut destinations = BTreeSet::new();
+    let mut items = Vec::new();
+
+    for (hash, score) in hits {
+        for (target, target_handle, other) in ctx.project.locate(&hash).await {
+            if &target == uri && other.start == block.start {
+                continue;
+            }
+
+            let heading = target_handle.heading_at(other.start.0);
+
+            let Some(path) = uri.make_relative(&target) else {
+                continue;
+            };
+            let (title, destination) = match heading {
+                Some(heading) => (heading.text, format!("{}#{}", path, heading.slug)),
+                None => (other.text.chars().take(40).collect::<String>(), path),
+            };
+
+            // Several blocks of a section lead to the same link, keep the closest one
+            if destination.is_empty() || !destinations.insert(destination.clone()) {
+                continue;
+            }
+
+            let file = target
+                .path_segments()
+                .and_then(|mut segments| segments.next_back())
+                .unwrap_or_default()
+                .to_string();
+
+            items.push(lsp_types::CompletionItem {
+                label: title.clone(),
+                kind: Some(lsp_types::CompletionItemKind::REFERENCE),
+                detail: Some(format!("{} ({:.2})", file, score)),
+                documentation: Some(lsp_types::Documentation::String(other.text)),
+                sort_text: Some(format!("{:05}", items.len())),
+                filter_text: Some(format!("[[{}", title)),
+                text_edit: Some(lsp_types::CompletionTextEdit::Edit(lsp_types::TextEdit {
+                    range,
+                    new_text: format!("[{}]({})", title, destination),
+                })),
+                ..Default::default()
+            });
+        }
+
+        if items.len() >= RELATED_LIMIT {
+            break;
+        }
+    }
+    items.truncate(RELATED_LIMIT);
+
+    Ok(items)
+}
+
+#[cfg(not(feature = "intelligence"))]
+async fn related(
+    _ctx: &crate::Backend,
+    _uri: &lsp_types::Url,
+    _handle: &crate::handler::Handle,
+    _typed: &str,
+    _position: lsp_types::Position,
+) -> anyhow::Result<Vec<lsp_types::CompletionItem>> {
+    Ok(vec![])
+}
+
+/// The range of the `typed` text right before the cursor, replaced by the completion.
+#[allow(clippy::as_conversions)]
+fn replace(position: lsp_types::Position, typed: &str) -> lsp_types::Range {
+    let length = typed.encode_utf16().count() as u32;
+
+    lsp_types::Range {
+        start: lsp_types::Position {
+            line: position.line,
+            character: position.character.saturating_sub(length),
+        },
+        end: position,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::ensure;
+
+    use super::Context;
+
+    #[test]
+    fn test_completion_context() -> anyhow::Result<()> {
+        ensure!(Context::parse("See [a](./docs/gu") == Some(Context::Path("./docs/gu")));
+        ensure!(
+            Context::parse("See [a](other.md#get")
+                == Some(Context::Anchor {
+                    path: "other.md",
+                    anchor: "get"
+                })
+        );
+        ensure!(
+            Context::parse("[a](#")
+                == Some(Context::Anchor {
+                    path: "",
+                    anchor: ""
+                })
+        );
+        ensure!(Context::parse("See [a][ref") == Some(Context::Reference("ref")));
+        ensure!(Context::parse("A note[^fo") == Some(Context::Footnote("fo")));
+        ensure!(Context::parse("As in [[emb") == Some(Context::Related("emb")));
+        ensure!(Context::parse("[a](done.md) and more").is_none());
+        ensure!(Context::parse("[a][ref] and").is_none());
+        ensure!(Context::parse("Plain text").is_none());
+
+        Ok(())
+    }
+}
@@ -1,8 +1,18 @@
-use crate::{embedding, llm};
+use crate::{embedding, lint, llm, schema, workspace};
+
+#[cfg(feature = "intelligence")]
+use crate::{analysis, handler::registry::cache};
 
 #[derive(Debug, Clone, serde::Serialize, serde::Deserialize, Default)]
 #[serde(default)]
 pub struct Config {
     pub embedding: embedding::Config,
     pub llm: llm::Config,
+    pub workspace: workspace::Config,
+    pub frontmatter: schema::Config,
+    pub lint: lint::Config,
+    #[cfg(feature = "intelligence")]
+    pub cache: cache::Config,
+    #[cfg(feature = "intelligence")]
+    pub analysis: analysis::Config,
 }
@@ -0,0 +1,85 @@
+use tower_lsp::lsp_types;
+
+use crate::handler::link;
+
+/// Jumps from a reference link to its definition, from a footnote to its note, and from a link
+/// (or a reference definition) to the heading or document it points at.
+pub async fn definition(
+    ctx: &crate::Backend,
+    params: lsp_types::GotoDefinitionParams,
+) -> anyhow::Result<Option<lsp_types::GotoDefinitionResponse>> {
+    let uri = params.text_document_position_params.text_document.uri;
+    let position = params.text_document_position_params.position;
+
+    let Some(handle) = ctx.project.handle(&uri).await else {
+        return Ok(None);
+    };
+
+    let (row, col) = handle.point(position);
+    let Some(link) = handle.link_at(row, col) else {
+        return Ok(None);
+    };
+
+    let location = match link.kind {
+        link::Kind::Reference | link::Kind::Footnote => {
+            let kind = match link.kind {
+                link::Kind::Reference => link::Kind::Definition,
+                _ => link::Kind::FootnoteDefinition,
+            };
+
+            handle
+                .links
+                .read()
+                .map_err(|_| anyhow::anyhow!("Failed while reading the links"))?
+                .iter()
+                .find(|definition| definition.kind == kind && definition.label == link.label)
+                .map(|definition| lsp_types::Location {
+                    uri: uri.clone(),
+                    range: handle.range(definition.start, definition.end),
+                })
+        }
+        link::Kind::Inline | link::Kind::Definition => match link.destination {
+            Some(destination) => target(ctx, &uri, &destination).await?,
+            None => None,
+        },
+        link::Kind::FootnoteDefinition => None,
+    };
+
+    Ok(location.map(lsp_types::GotoDefinitionResponse::Scalar))
+}
+
+/// Where a link destination leads: the heading matching its anchor, or the top of the target
+/// document when there is no anchor (or it matches no heading).
+pub async fn target(
+    ctx: &crate::Backend,
+    base: &lsp_types::Url,
+    destination: &link::Destination,
+) -> anyhow::Result<Option<lsp_types::Location>> {
+    let Some(uri) = destination.resolve(base) else {
+        return Ok(None);
+    };
+
+    let handle = ctx.project.handle(&uri).await;
+    let heading = match (&handle, destination.slug()) {
+        (Some(handle), Some(anchor)) => handle
+            .headings
+            .read()
+            .map_err(|_| anyhow::anyhow!("Failed while reading the headings"))?
+            .iter()
+            .find(|heading| heading.slug == anchor)
+            .cloned(),
+        (Some(_), None) => None,
+        // Neither open nor indexed, only jump to files that exist on disk
+        (None, _) => match uri.to_file_path() {
+            Ok(path) if tokio::fs::try_exists(&path).await.unwrap_or(false) => None,
+            _ => return Ok(None),
+        },
+    };
+
+    let range = match (heading, handle) {
+        (Some(heading), Some(handle)) => handle.range(heading.start, heading.end),
+        _ => lsp_types::Range::default(),
+    };
+
+    Ok(Some(lsp_types::Location { uri, range }))
+}
@@ -0,0 +1,234 @@
+use tower_lsp::lsp_types;
+
+use crate::{handler, lint};
+
+const SOURCE: &str = "marky";
+
+/// Computes the diagnostics of an open document and publishes them to the client.
+pub async fn publish(ctx: &crate::Backend, uri: lsp_types::Url) -> anyhow::Result<()> {
+    let Some(handle) = ctx.project.documents.get(&uri).await else {
+        return Ok(());
+    };
+
+    let mut diagnostics = links(ctx, &uri, &handle).await?;
+    diagnostics.extend(frontmatter(ctx, &handle)?);
+    diagnostics.extend(prose(ctx, &handle)?);
+
+    ctx.client.publish_diagnostics(uri, diagnostics, None).await;
+
+    Ok(())
+}
+
+/// Republishes the diagnostics of every open document, e.g. once the workspace index is ready.
+pub async fn refresh(ctx: &crate::Backend) -> anyhow::Result<()> {
+    for (uri, _) in ctx.project.documents.snapshot().await {
+        publish(ctx, uri).await?;
+    }
+
+    Ok(())
+}
+
+/// Republishes the diagnostics of the other open documents linking to `uri`, e.g. once its
+/// headings changed.
+pub async fn dependents(ctx: &crate::Backend, uri: &lsp_types::Url) -> anyhow::Result<()> {
+    for (other, handle) in ctx.project.documents.snapshot().await {
+        if &other == uri {
+            continue;
+        }
+
+        let linking = handle
+            .links
+            .read()
+            .map_err(|_| anyhow::anyhow!("Failed while reading the links"))?
+            .iter()
+            .filter_map(|link| link.destination.as_ref())
+            .any(|destination| destination.resolve(&other).as_ref() == Some(uri));
+
+        if linking {
+            publish(ctx, other).await?;
+        }
+    }
+
+    Ok(())
+}
+
+/// The anchors of the headings of a document, the targets links into it are checked against.
+pub fn anchors(handle: &handler::Handle) -> Vec<String> {
+    handle
+        .headings
+        .read()
+        .map(|headings| {
+            headings
+                .iter()
+                .map(|heading| heading.slug.clone())
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+pub async fn clear(ctx: &crate::Backend, uri: lsp_types::Url) {
+    ctx.client.publish_diagnostics(uri, vec![], None).await;
+}
+
+/// Relative links whose target file does not exist, or whose anchor matches no heading of the
+/// target document.
+async fn links(
+    ctx: &crate::Backend,
+    uri: &lsp_types::Url,
+    handle: &handler::Handle,
+) -> anyhow::Result<Vec<lsp_types::Diagnostic>> {
+    let links = handle
+        .links
+        .read()
+        .map_err(|_| anyhow::anyhow!("Failed while reading the links"))?
+        .clone();
+
+    let mut diagnostics = Vec::new();
+
+    for destination in links.into_iter().filter_map(|link| link.destination) {
+        let Some(target) = destination.resolve(uri) else {
+            continue;
+        };
+
+        let (code, message) = match ctx.project.handle(&target).await {
+            Some(target_handle) => {
+                let Some(anchor) = destination.anchor().filter(|anchor| !anchor.is_empty()) else {
+                    continue;
+                };
+
+                let found = target_handle
+                    .headings
+                    .read()
+                    .map_err(|_| anyhow::anyhow!("Failed while reading the headings"))?
+                    .iter()
+                    .any(|heading| Some(&heading.slug) == destination.slug().as_ref());
+                if found {
+                    continue;
+                }
+
+                let message = match destination.path() {
+                    "" => format!("No heading with anchor `#{}` in this document", anchor),
+                    path => format!("No heading with anchor `#{}` in `{}`", anchor, path),
+                };
+                ("missing-anchor", message)
+            }
+            None => {
+                if exists(&target).await {
+                    continue;
+                }
+
+                (
+                    "broken-link",
+                    format!("Linked file `{}` does not exist", destination.path()),
+                )
+            }
+        };
+
+        diagnostics.push(lsp_types::Diagnostic {
+            range: handle.range(destination.start, destination.end),
+            severity: Some(lsp_types::DiagnosticSeverity::WARNING),
+            code: Some(lsp_types::NumberOrString::String(code.to_string())),
+            source: Some(SOURCE.to_string()),
+            message,
+            ..Default::default()
+        });
+    }
+
+    Ok(diagnostics)
+}
+
+/// Frontmatter that does not parse, or does not match the configured schema.
+fn frontmatter(
+    ctx: &crate::Backend,
+    handle: &handler::Handle,
+) -> anyhow::Result<Vec<lsp_types::Diagnostic>> {
+    let Some(frontmatter) = handle
+        .frontmatter
+        .read()
+        .map_err(|_| anyhow::anyhow!("Failed while reading the frontmatter"))?
+        .clone()
+    else {
+        return Ok(vec![]);
+    };
+
+    if let Some(error) = frontmatter.error {
+        return Ok(vec![lsp_types::Diagnostic {
+            range: handle.range(error.position, error.position),
+            severity: Some(lsp_types::DiagnosticSeverity::ERROR),
+            code: Some(lsp_types::NumberOrString::String(
+                "invalid-frontmatter".to_string(),
+            )),
+            source: Some(SOURCE.to_string()),
+            message: error.message,
+            ..Default::default()
+        }]);
+    }
+
+    let Some(schema) = ctx.schema.get() else {
+        return Ok(vec![]);
+    };
+
+    // Violations about the frontmatter as a whole go on its opening delimiter
+    let opening = handle.range((frontmatter.span.0, 0), (frontmatter.span.0, 3));
+
+    Ok(schema
+        .validate(&frontmatter.data)
+        .into_iter()
+        .map(|violation| lsp_types::Diagnostic {
+            range: violation
+                .key
+                .as_deref()
+                .and_then(|key| frontmatter.key(key))
+                .map_or(opening, |key| handle.range(key.start, key.end)),
+            severity: Some(lsp_types::DiagnosticSeverity::WARNING),
+            code: Some(lsp_types::NumberOrString::String(
+                violation.code.as_str().to_string(),
+            )),
+            source: Some(SOURCE.to_string()),
+            message: violation.message,
+            ..Default::default()
+        })
+        .collect())
+}
+
+/// Findings of the prose linter.
+fn prose(
+    ctx: &crate::Backend,
+    handle: &handler::Handle,
+) -> anyhow::Result<Vec<lsp_types::Diagnostic>> {
+    let Some(linter) = ctx.linter.get() else {
+        return Ok(vec![]);
+    };
+
+    Ok(linter
+        .check(handle)?
+        .into_iter()
+        .filter_map(|finding| {
+            let severity = match finding.level {
+                lint::Level::Off => return None,
+                lint::Level::Hint => lsp_types::DiagnosticSeverity::HINT,
+                lint::Level::Info => lsp_types::DiagnosticSeverity::INFORMATION,
+                lint::Level::Warning => lsp_types::DiagnosticSeverity::WARNING,
+                lint::Level::Error => lsp_types::DiagnosticSeverity::ERROR,
+            };
+
+            Some(lsp_types::Diagnostic {
+                range: handle.range(finding.start, finding.end),
+                severity: Some(severity),
+                code: Some(lsp_types::NumberOrString::String(finding.rule)),
+                source: Some(SOURCE.to_string()),
+                message: finding.message,
+                ..Default::default()
+            })
+        })
+        .collect())
+}
+
+/// Targets that are neither open nor indexed may still exist on disk, e.g. images or files
+/// excluded from the index. Non-file targets are assumed to exist.
+async fn exists(target: &lsp_types::Url) -> bool {
+    match target.to_file_path() {
+        Ok(path) => tokio::fs::try_exists(path).await.unwrap_or(false),
+        Err(_) => true,
+    }
+}
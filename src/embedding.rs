@@ -11,6 +11,8 @@ pub trait Embedding: Sync + Send {
     }
     async fn embed_multiple(&self, texts: Vec<String>) -> anyhow::Result<Vec<Vec<f32>>>;
     fn size(&self) -> usize;
+    /// Name of the model producing the embeddings, vectors of different models do not compare.
+    fn model(&self) -> &str;
 }
 
 mod ollama;
@@ -53,4 +55,10 @@ impl Embedding for Client {
             Self::Ollama(client) => client.size(),
         }
     }
+
+    fn model(&self) -> &str {
+        match self {
+            Self::Ollama(client) => client.model(),
+        }
+    }
 }
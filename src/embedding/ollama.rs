@@ -51,4 +51,8 @@ impl super::Embedding for Client {
     fn size(&self) -> usize {
         self.config.vector_size
     }
+
+    fn model(&self) -> &str {
+        &self.config.model
+    }
 }
@@ -0,0 +1,99 @@
+use tower_lsp::lsp_types;
+
+use crate::handler;
+
+/// Lists spanning fewer lines than this are not worth folding.
+const LIST_FOLD_LINES: usize = 4;
+
+/// Folds sections (from a heading to the next heading of the same or a higher level), fenced and
+/// indented code, block quotes, long lists, HTML blocks and the frontmatter.
+pub async fn folding_range(
+    ctx: &crate::Backend,
+    params: lsp_types::FoldingRangeParams,
+) -> anyhow::Result<Option<Vec<lsp_types::FoldingRange>>> {
+    let Some(handle) = ctx.project.handle(&params.text_document.uri).await else {
+        return Ok(None);
+    };
+
+    let mut ranges = Vec::new();
+
+    if let Some((start, end)) = handler::frontmatter::span(&handle.text()) {
+        push(
+            &mut ranges,
+            start,
+            end,
+            Some(lsp_types::FoldingRangeKind::Region),
+        );
+    }
+
+    let headings = handle
+        .headings
+        .read()
+        .map_err(|_| anyhow::anyhow!("Failed while reading the headings"))?
+        .clone();
+    let ends = handler::heading::section_ends(&headings, handle.end());
+
+    for (heading, end) in headings.iter().zip(ends) {
+        push(
+            &mut ranges,
+            heading.start.0,
+            last_row(end),
+            Some(lsp_types::FoldingRangeKind::Region),
+        );
+    }
+
+    if let Some(tree) = handle.tree() {
+        visit(&tree.root_node(), &mut ranges);
+    }
+
+    Ok(Some(ranges))
+}
+
+fn visit(node: &tree_sitter::Node<'_>, ranges: &mut Vec<lsp_types::FoldingRange>) {
+    let start = node.start_position().row;
+    let end = last_row((node.end_position().row, node.end_position().column));
+
+    match node.kind() {
+        "fenced_code_block" | "indented_code_block" | "block_quote" | "html_block" => {
+            push(ranges, start, end, None);
+        }
+        "list" if end.saturating_sub(start) + 1 >= LIST_FOLD_LINES => {
+            push(ranges, start, end, None)
+        }
+        _ => {}
+    }
+
+    let mut cursor = node.walk();
+    for child in node.children(&mut cursor) {
+        visit(&child, ranges);
+    }
+}
+
+/// Nodes usually end at the start of the line following them, which is not part of the region.
+fn last_row(end: (usize, usize)) -> usize {
+    match end {
+        (row, 0) => row.saturating_sub(1),
+        (row, _) => row,
+    }
+}
+
+fn push(
+    ranges: &mut Vec<lsp_types::FoldingRange>,
+    start: usize,
+    end: usize,
+    kind: Option<lsp_types::FoldingRangeKind>,
+) {
+    if end <= start {
+        return;
+    }
+
+    #[allow(clippy::as_conversions)]
+    ranges.push(lsp_types::FoldingRange {
+        start_line: start as u32,
+        start_character: None,
+        end_line: end as u32,
+        end_character: None,
+        kind,
+        collapsed_text: None,
+    });
+}
@@ -1,14 +1,42 @@
+use std::collections::BTreeMap;
 use std::sync::Arc;
 
-use std::sync::RwLock;
+use std::sync::{Mutex, RwLock};
 
+use tower_lsp::lsp_types;
+
+pub mod block;
+mod edit;
+pub mod frontmatter;
+pub mod heading;
+pub mod link;
 #[cfg(feature = "intelligence")]
 pub mod registry;
+mod stat;
+
+pub use block::BlockKind;
+pub use frontmatter::Frontmatter;
+pub use heading::Heading;
+pub use link::Link;
 
 #[derive(Debug, Clone)]
 pub struct Handle {
+    text: Arc<RwLock<ropey::Rope>>,
     tree: Arc<RwLock<tree_sitter::Tree>>,
     pub blocks: Arc<RwLock<Vec<Block>>>,
+    pub headings: Arc<RwLock<Vec<Heading>>>,
+    pub links: Arc<RwLock<Vec<Link>>>,
+    pub frontmatter: Arc<RwLock<Option<Frontmatter>>>,
+    versions: Arc<Mutex<Versions>>,
+}
+
+/// Versions of the document as reported by the client. Changes can reach the server out of
+/// order, those ahead of the next version wait until the versions before them are applied.
+#[derive(Debug, Default)]
+struct Versions {
+    /// `None` until the client reports a version, documents read from disk have none.
+    current: Option<i32>,
+    pending: BTreeMap<i32, Vec<lsp_types::TextDocumentContentChangeEvent>>,
 }
 
 #[derive(Debug, Clone)]
@@ -16,16 +44,29 @@ pub struct Block {
     pub text: String,
     pub start: (usize, usize),
     pub end: (usize, usize),
+    pub kind: BlockKind,
     pub stat: Stat,
     pub metadata: Metadata,
 }
 
 #[derive(Debug, Clone)]
 pub struct Stat {
+    /// Sentences for prose, lines for code and markup.
     pub lines: usize,
     pub words: usize,
+    /// Words per line.
     pub avg_line_size: f32,
-    // TODO: pub avg_word_size: f32,
+    /// Characters, whitespace excluded.
+    pub characters: usize,
+    /// Characters per word.
+    pub avg_word_size: f32,
+    /// Estimated from the vowel groups of each word.
+    pub syllables: usize,
+    /// Flesch reading ease, the higher the easier, most prose lands between 0 and 100.
+    pub reading_ease: f32,
+    /// Flesch-Kincaid grade level, the years of schooling needed to follow the text.
+    pub grade_level: f32,
+    pub reading_time: std::time::Duration,
 }
 
 #[derive(Debug, Clone)]
@@ -39,83 +80,226 @@ impl Handle {
             .parse(text, None)
             .ok_or_else(|| anyhow::anyhow!("Failed to parse the text with the parser"))?;
         let root_node = tree.root_node();
-        let mut blocks = Vec::new();
-        Self::construct_blocks(&root_node, &mut blocks, text)?;
+        let blocks = block::collect(&root_node, text, &mut Default::default())?;
+        let headings = heading::collect(&root_node, text)?;
+        let links = link::collect(&root_node, text)?;
+        let frontmatter = frontmatter::parse(text);
 
+        let text = Arc::new(RwLock::new(ropey::Rope::from_str(text)));
         let blocks = Arc::new(RwLock::new(blocks));
+        let headings = Arc::new(RwLock::new(headings));
+        let links = Arc::new(RwLock::new(links));
+        let frontmatter = Arc::new(RwLock::new(frontmatter));
         let tree = Arc::new(RwLock::new(tree));
 
-        Ok(Self { tree, blocks })
+        Ok(Self {
+            text,
+            tree,
+            blocks,
+            headings,
+            links,
+            frontmatter,
+            versions: Default::default(),
+        })
+    }
+
+    /// Sets the version of the text the handle was created from.
+    pub fn set_version(&self, version: i32) -> anyhow::Result<()> {
+        let mut versions = self
+            .versions
+            .lock()
+            .map_err(|_| anyhow::anyhow!("Failed while locking the versions"))?;
+        *versions = Versions {
+            current: Some(version),
+            ..Default::default()
+        };
+
+        Ok(())
+    }
+
+    /// Applies the content changes bringing the document to `version`. Changes for a later
+    /// version than the next one are held back until the versions in between arrive, changes for
+    /// a version already applied are rejected.
+    pub fn update(
+        &mut self,
+        version: i32,
+        changes: &[lsp_types::TextDocumentContentChangeEvent],
+        parser: &mut tree_sitter::Parser,
+    ) -> anyhow::Result<()> {
+        let versions = self.versions.clone();
+        let mut versions = versions
+            .lock()
+            .map_err(|_| anyhow::anyhow!("Failed while locking the versions"))?;
+
+        match versions.current {
+            Some(current) if version <= current => {
+                anyhow::bail!("Stale version {}, the document is at {}", version, current)
+            }
+            Some(current) if version > current + 1 => {
+                versions.pending.insert(version, changes.to_vec());
+                return Ok(());
+            }
+            _ => {}
+        }
+
+        self.apply(changes, parser)?;
+        versions.current = Some(version);
+
+        let mut next = version + 1;
+        while let Some(changes) = versions.pending.remove(&next) {
+            self.apply(&changes, parser)?;
+            versions.current = Some(next);
+            next += 1;
+        }
+
+        Ok(())
     }
-    pub fn update(&mut self, text: &str, parser: &mut tree_sitter::Parser) -> anyhow::Result<()> {
+
+    /// Applies the content changes in order and reparses incrementally, blocks whose span was
+    /// not touched by any of the changes are carried over instead of being rebuilt.
+    fn apply(
+        &self,
+        changes: &[lsp_types::TextDocumentContentChangeEvent],
+        parser: &mut tree_sitter::Parser,
+    ) -> anyhow::Result<()> {
+        let mut rope = self
+            .text
+            .write()
+            .map_err(|_| anyhow::anyhow!("Failed while writing to the text"))?;
         let mut tree = self
             .tree
             .write()
             .map_err(|_| anyhow::anyhow!("Failed while writing to the tree"))?;
-        *tree = parser
-            .parse(text, Some(&tree))
+
+        let edits = changes
+            .iter()
+            .map(|change| {
+                let edit = edit::apply(&mut rope, change);
+                tree.edit(&edit);
+                edit
+            })
+            .collect::<Vec<_>>();
+
+        let text = rope.to_string();
+        let new_tree = parser
+            .parse(&text, Some(&tree))
             .ok_or_else(|| anyhow::anyhow!("Failed to parse the text with the parser"))?;
 
         let mut blocks = self
             .blocks
             .write()
             .map_err(|_| anyhow::anyhow!("Failed while writing to the blocks"))?;
-        blocks.clear();
+        let mut retained = edit::Retained::new(
+            std::mem::take(&mut *blocks),
+            &edits,
+            tree.changed_ranges(&new_tree),
+        );
+        *tree = new_tree;
+
         let root_node = tree.root_node();
-        Self::construct_blocks(&root_node, &mut blocks, text)?;
+        *blocks = block::collect(&root_node, &text, &mut retained)?;
+
+        *self
+            .headings
+            .write()
+            .map_err(|_| anyhow::anyhow!("Failed while writing to the headings"))? =
+            heading::collect(&root_node, &text)?;
+
+        *self
+            .links
+            .write()
+            .map_err(|_| anyhow::anyhow!("Failed while writing to the links"))? =
+            link::collect(&root_node, &text)?;
+
+        *self
+            .frontmatter
+            .write()
+            .map_err(|_| anyhow::anyhow!("Failed while writing to the frontmatter"))? =
+            frontmatter::parse(&text);
 
         Ok(())
     }
 
-    fn construct_blocks(
-        node: &tree_sitter::Node<'_>,
-        blocks: &mut Vec<Block>,
-        text: &str,
-    ) -> Result<(), anyhow::Error> {
-        if node.kind() == "paragraph" || node.kind() == "heading_content" {
-            let text = node.utf8_text(text.as_bytes())?.trim();
-            let start = (node.start_position().row, node.start_position().column);
-            let end = (node.end_position().row, node.end_position().column);
-            let lines = text.split('.').filter(|line| !line.is_empty());
-            let line_count = lines.clone().count();
-            let total_word_count: usize = lines
-                .map(|line| {
-                    line.split_whitespace()
-                        .filter(|word| !word.is_empty())
-                        .count()
-                })
-                .sum();
-
-            #[allow(clippy::as_conversions)]
-            let avg_words_per_line = total_word_count as f32 / line_count as f32;
-
-            let block = Block {
-                text: text.to_string(),
-                start,
-                end,
-                stat: Stat {
-                    lines: line_count,
-                    words: total_word_count,
-                    avg_line_size: avg_words_per_line,
-                },
-                metadata: Metadata {
-                    hash: *blake3::hash(text.as_bytes()).as_bytes(),
-                },
-            };
+    /// The current text of the whole document.
+    pub fn text(&self) -> String {
+        self.text
+            .read()
+            .map(|rope| rope.to_string())
+            .unwrap_or_default()
+    }
 
-            blocks.push(block);
+    /// A copy of the current syntax tree, copies are cheap and share their nodes.
+    pub fn tree(&self) -> Option<tree_sitter::Tree> {
+        self.tree.read().ok().map(|tree| tree.clone())
+    }
 
-            Ok(())
-        } else {
-            let mut cursor = node.walk();
-            for child in node.children(&mut cursor) {
-                Self::construct_blocks(&child, blocks, text)?;
+    /// Position right after the last character of the document.
+    pub fn end(&self) -> (usize, usize) {
+        match self.tree.read() {
+            Ok(tree) => {
+                let end = tree.root_node().end_position();
+                (end.row, end.column)
             }
+            Err(_) => (0, 0),
+        }
+    }
+
+    /// Converts a `(row, byte column)` point of the document into an LSP position, which counts
+    /// UTF-16 code units.
+    pub fn position(&self, point: (usize, usize)) -> lsp_types::Position {
+        match self.text.read() {
+            Ok(rope) => edit::position(&rope, point),
+            Err(_) => lsp_types::Position::default(),
+        }
+    }
+
+    /// The LSP range between two `(row, byte column)` points of the document.
+    pub fn range(&self, start: (usize, usize), end: (usize, usize)) -> lsp_types::Range {
+        lsp_types::Range {
+            start: self.position(start),
+            end: self.position(end),
+        }
+    }
 
-            Ok(())
+    /// Converts an LSP position into the `(row, byte column)` point of the document it refers
+    /// to, positions past the end of a line clamp to its end.
+    pub fn point(&self, position: lsp_types::Position) -> (usize, usize) {
+        match self.text.read() {
+            Ok(rope) => edit::byte_point(&rope, position),
+            Err(_) => (0, 0),
         }
     }
 
+    /// The current text of the document within an LSP range.
+    pub fn text_in(&self, range: lsp_types::Range) -> Option<String> {
+        let rope = self.text.read().ok()?;
+        let start = edit::char_index(&rope, range.start);
+        let end = edit::char_index(&rope, range.end).max(start);
+
+        Some(rope.slice(start..end).to_string())
+    }
+
+    /// The heading of the section `row` belongs to, if any.
+    pub fn heading_at(&self, row: usize) -> Option<Heading> {
+        let headings = self.headings.read().ok()?;
+
+        headings
+            .iter()
+            .take_while(|heading| heading.start.0 <= row)
+            .last()
+            .cloned()
+    }
+
+    /// The link, reference definition or footnote under the cursor, if any.
+    pub fn link_at(&self, row: usize, col: usize) -> Option<Link> {
+        let links = self.links.read().ok()?;
+
+        links
+            .iter()
+            .find(|link| link.start <= (row, col) && (row, col) <= link.end)
+            .cloned()
+    }
+
     #[allow(clippy::indexing_slicing)]
     pub fn get_block(&self, target_row: usize, target_col: usize) -> Option<Block> {
         let blocks = match self.blocks.read() {
@@ -156,6 +340,7 @@ impl Handle {
 #[cfg(test)]
 mod tests {
     use anyhow::ensure;
+    use tower_lsp::lsp_types;
 
     #[allow(clippy::indexing_slicing)]
     #[tokio::test]
@@ -201,6 +386,135 @@ mod tests {
         Ok(())
     }
 
+    #[tokio::test]
+    async fn test_incremental_update() -> anyhow::Result<()> {
+        let code = [
+            "# Title",
+            "",
+            "Hello, world!",
+            "",
+            "## Subtitle",
+            "",
+            "Lorem ipsum dolor sit amet, consectetur adipiscing elit.",
+            "",
+        ]
+        .join("\n");
+
+        let mut parser = tree_sitter::Parser::new();
+        parser.set_language(tree_sitter_md::language())?;
+
+        let mut handle = super::Handle::new(&code, &mut parser)?;
+
+        let change = |line, start, end, text: &str| lsp_types::TextDocumentContentChangeEvent {
+            range: Some(lsp_types::Range {
+                start: lsp_types::Position {
+                    line,
+                    character: start,
+                },
+                end: lsp_types::Position {
+                    line,
+                    character: end,
+                },
+            }),
+            range_length: None,
+            text: text.to_string(),
+        };
+
+        handle.update(
+            1,
+            &[
+                // Past the end of the line, clamps to the end of that line
+                change(4, 11, 99, "!"),
+                change(2, 7, 12, "there"),
+                change(0, 2, 7, "Heading\n\nIntro paragraph."),
+            ],
+            &mut parser,
+        )?;
+
+        let expected = code
+            .replace("## Subtitle", "## Subtitle!")
+            .replace("world", "there")
+            .replace("# Title", "# Heading\n\nIntro paragraph.");
+        let fresh = super::Handle::new(&expected, &mut parser)?;
+
+        let blocks = handle
+            .blocks
+            .read()
+            .map_err(|_| anyhow::anyhow!("Failed while reading the blocks"))?;
+        let fresh = fresh
+            .blocks
+            .read()
+            .map_err(|_| anyhow::anyhow!("Failed while reading the blocks"))?;
+
+        ensure!(blocks.len() == fresh.len());
+        for (updated, fresh) in blocks.iter().zip(fresh.iter()) {
+            ensure!(updated.text == fresh.text);
+            ensure!(updated.start == fresh.start);
+            ensure!(updated.end == fresh.end);
+            ensure!(updated.metadata.hash == fresh.metadata.hash);
+        }
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_out_of_order_update() -> anyhow::Result<()> {
+        let mut parser = tree_sitter::Parser::new();
+        parser.set_language(tree_sitter_md::language())?;
+
+        let handle = super::Handle::new("Hello, world!\n", &mut parser)?;
+        handle.set_version(1)?;
+
+        let change = |start, end, text: &str| lsp_types::TextDocumentContentChangeEvent {
+            range: Some(lsp_types::Range {
+                start: lsp_types::Position {
+                    line: 0,
+                    character: start,
+                },
+                end: lsp_types::Position {
+                    line: 0,
+                    character: end,
+                },
+            }),
+            range_length: None,
+            text: text.to_string(),
+        };
+
+        // Version 3 only makes sense once "world" became "there" in version 2
+        let mut late = handle.clone();
+        late.update(3, &[change(7, 12, "there, again")], &mut parser)?;
+        ensure!(handle.text() == "Hello, world!\n");
+
+        late.update(2, &[change(7, 12, "there")], &mut parser)?;
+        ensure!(handle.text() == "Hello, there, again!\n");
+
+        ensure!(late.update(2, &[change(0, 5, "Bye")], &mut parser).is_err());
+        ensure!(handle.text() == "Hello, there, again!\n");
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_utf16_positions() -> anyhow::Result<()> {
+        let code = "# Café\n\nÜber 😀 [link](a.md)\n";
+
+        let mut parser = tree_sitter::Parser::new();
+        parser.set_language(tree_sitter_md::language())?;
+        let handle = super::Handle::new(code, &mut parser)?;
+
+        // `Ü` is 2 bytes and 1 code unit, `😀` 4 bytes and 2 code units
+        let link = (2, "Über 😀 ".len());
+        let position = handle.position(link);
+        ensure!((position.line, position.character) == (2, 8));
+        ensure!(handle.point(position) == link);
+
+        // Past the end of the line clamps to its end, not to the next line
+        let end = handle.position((0, 99));
+        ensure!((end.line, end.character) == (0, 6));
+
+        Ok(())
+    }
+
     // #[tokio::test]
     // async fn test_get_block() -> anyhow::Result<()> {
     //     let code = [
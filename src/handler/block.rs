@@ -0,0 +1,446 @@
+use super::{Block, Metadata, edit, frontmatter, heading, stat};
+
+/// What a block is made of, along with the details hover and the analysis care about.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BlockKind {
+    Paragraph,
+    /// Text of an ATX or setext heading.
+    Heading {
+        level: usize,
+    },
+    /// Paragraph of a list item, `depth` is 1 for top level lists.
+    ListItem {
+        depth: usize,
+        ordered: bool,
+    },
+    /// Cell of a GitHub style table, row 0 is the header.
+    TableCell {
+        row: usize,
+        column: usize,
+    },
+    /// Fenced or indented code.
+    Code {
+        language: Option<String>,
+    },
+    /// Paragraph of a block quote, `depth` is 1 for top level quotes.
+    BlockQuote {
+        depth: usize,
+    },
+    Html,
+    /// YAML or TOML frontmatter, without its delimiters.
+    Frontmatter,
+}
+
+/// Which kinds of analysis a block goes through.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Policy {
+    pub keywords: bool,
+    /// Long blocks only, see the registry.
+    pub summary: bool,
+    pub embedding: bool,
+}
+
+impl BlockKind {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Paragraph => "paragraph",
+            Self::Heading { .. } => "heading",
+            Self::ListItem { .. } => "list_item",
+            Self::TableCell { .. } => "table_cell",
+            Self::Code { .. } => "code",
+            Self::BlockQuote { .. } => "block_quote",
+            Self::Html => "html",
+            Self::Frontmatter => "frontmatter",
+        }
+    }
+
+    /// Whether the block is written in natural language, as opposed to code or markup.
+    pub fn is_prose(&self) -> bool {
+        !matches!(self, Self::Code { .. } | Self::Html | Self::Frontmatter)
+    }
+
+    pub fn policy(&self) -> Policy {
+        match self {
+            Self::Paragraph | Self::BlockQuote { .. } => Policy {
+                keywords: true,
+                summary: true,
+                embedding: true,
+            },
+            Self::Heading { .. } | Self::ListItem { .. } => Policy {
+                keywords: true,
+                summary: false,
+                embedding: true,
+            },
+            Self::TableCell { .. } | Self::Code { .. } | Self::Html | Self::Frontmatter => Policy {
+                keywords: false,
+                summary: false,
+                embedding: false,
+            },
+        }
+    }
+}
+
+/// Collects the blocks of the document in order, reusing the retained blocks of the previous
+/// parse where possible.
+pub(super) fn collect(
+    node: &tree_sitter::Node<'_>,
+    text: &str,
+    retained: &mut edit::Retained,
+) -> anyhow::Result<Vec<Block>> {
+    let mut blocks = Vec::new();
+
+    // The grammar knows nothing about frontmatter and parses it as a thematic break followed by
+    // a heading, the rows are taken over by a single block
+    let frontmatter = frontmatter::span(text);
+    if let Some((_, last)) = frontmatter {
+        let lines = text.lines().take(last + 1).collect::<Vec<_>>();
+        let inner = lines
+            .get(1..last)
+            .map(|lines| lines.join("\n"))
+            .unwrap_or_default();
+        let end = (last, lines.last().map_or(0, |line| line.len()));
+
+        push(
+            &mut blocks,
+            retained,
+            BlockKind::Frontmatter,
+            (0, 0),
+            end,
+            &inner,
+        );
+    }
+
+    visit(
+        node,
+        text,
+        frontmatter.map(|(_, last)| last),
+        &mut blocks,
+        retained,
+    )?;
+
+    Ok(blocks)
+}
+
+fn visit(
+    node: &tree_sitter::Node<'_>,
+    text: &str,
+    frontmatter: Option<usize>,
+    blocks: &mut Vec<Block>,
+    retained: &mut edit::Retained,
+) -> anyhow::Result<()> {
+    if frontmatter.is_some_and(|last| node.start_position().row <= last)
+        && node.kind() != "document"
+    {
+        return Ok(());
+    }
+
+    let start = (node.start_position().row, node.start_position().column);
+    let end = (node.end_position().row, node.end_position().column);
+
+    match node.kind() {
+        "heading_content" => {
+            let kind = BlockKind::Heading {
+                level: node.parent().map_or(1, |parent| level(&parent)),
+            };
+            let content = heading::strip_closing_sequence(node.utf8_text(text.as_bytes())?.trim());
+            push(blocks, retained, kind, start, end, content);
+        }
+        "paragraph" => {
+            let kind = container(node);
+            let content = node.utf8_text(text.as_bytes())?;
+
+            match kind == BlockKind::Paragraph && is_table(content) {
+                true => cells(blocks, retained, start, content),
+                false => push(blocks, retained, kind, start, end, content.trim()),
+            }
+        }
+        "fenced_code_block" => {
+            let mut language = None;
+            let mut content = "";
+
+            let mut cursor = node.walk();
+            for child in node.children(&mut cursor) {
+                match child.kind() {
+                    "info_string" => {
+                        let mut cursor = child.walk();
+                        language = child
+                            .children(&mut cursor)
+                            .find(|child| child.kind() == "language")
+                            .and_then(|language| language.utf8_text(text.as_bytes()).ok())
+                            .map(str::to_string);
+                    }
+                    "code_fence_content" => content = child.utf8_text(text.as_bytes())?,
+                    _ => {}
+                }
+            }
+
+            let kind = BlockKind::Code { language };
+            push(blocks, retained, kind, start, end, content.trim_end());
+        }
+        "indented_code_block" => {
+            let content = node.utf8_text(text.as_bytes())?.trim_end();
+            let kind = BlockKind::Code { language: None };
+            push(blocks, retained, kind, start, end, content);
+        }
+        "html_block" => {
+            let content = node.utf8_text(text.as_bytes())?.trim();
+            push(blocks, retained, BlockKind::Html, start, end, content);
+        }
+        _ => {
+            let mut cursor = node.walk();
+            for child in node.children(&mut cursor) {
+                visit(&child, text, frontmatter, blocks, retained)?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn push(
+    blocks: &mut Vec<Block>,
+    retained: &mut edit::Retained,
+    kind: BlockKind,
+    start: (usize, usize),
+    end: (usize, usize),
+    text: &str,
+) {
+    // An edit elsewhere can move a block into a list or a table without touching its span
+    if let Some(block) = retained.take(start, end)
+        && block.kind == kind
+    {
+        blocks.push(block);
+        return;
+    }
+
+    if text.is_empty() {
+        return;
+    }
+
+    blocks.push(Block {
+        text: text.to_string(),
+        start,
+        end,
+        stat: stat::measure(&kind, text),
+        kind,
+        metadata: Metadata {
+            hash: *blake3::hash(text.as_bytes()).as_bytes(),
+        },
+    });
+}
+
+/// Level of an ATX or setext heading node.
+fn level(node: &tree_sitter::Node<'_>) -> usize {
+    let mut cursor = node.walk();
+    let level = node
+        .children(&mut cursor)
+        .find_map(|child| match child.kind() {
+            "atx_h1_marker" | "setext_h1_underline" => Some(1),
+            "atx_h2_marker" | "setext_h2_underline" => Some(2),
+            "atx_h3_marker" => Some(3),
+            "atx_h4_marker" => Some(4),
+            "atx_h5_marker" => Some(5),
+            "atx_h6_marker" => Some(6),
+            _ => None,
+        });
+
+    level.unwrap_or(1)
+}
+
+/// The kind of a paragraph, decided by the closest list item or block quote containing it.
+fn container(node: &tree_sitter::Node<'_>) -> BlockKind {
+    let mut kind = BlockKind::Paragraph;
+
+    let mut parent = node.parent();
+    while let Some(node) = parent {
+        match (node.kind(), &mut kind) {
+            ("list_item", BlockKind::Paragraph) => {
+                let mut cursor = node.walk();
+                let ordered = node.children(&mut cursor).any(|child| {
+                    matches!(child.kind(), "list_marker_dot" | "list_marker_parenthesis")
+                });
+                kind = BlockKind::ListItem { depth: 0, ordered };
+            }
+            ("list", BlockKind::ListItem { depth, .. }) => *depth += 1,
+            ("block_quote", BlockKind::Paragraph) => kind = BlockKind::BlockQuote { depth: 1 },
+            ("block_quote", BlockKind::BlockQuote { depth }) => *depth += 1,
+            _ => {}
+        }
+
+        parent = node.parent();
+    }
+
+    kind
+}
+
+/// Whether a paragraph is a table, i.e. its second line is a delimiter row like `| --- | :-: |`.
+fn is_table(text: &str) -> bool {
+    let mut lines = text.lines();
+    let (Some(header), Some(delimiter)) = (lines.next(), lines.next()) else {
+        return false;
+    };
+
+    let delimiter = delimiter.trim();
+    header.contains('|')
+        && delimiter.contains('-')
+        && delimiter
+            .chars()
+            .all(|c| matches!(c, '|' | '-' | ':' | ' ' | '\t'))
+}
+
+/// Pushes a block per non-empty cell of a table paragraph starting at `start`.
+fn cells(
+    blocks: &mut Vec<Block>,
+    retained: &mut edit::Retained,
+    start: (usize, usize),
+    text: &str,
+) {
+    let rows = text
+        .lines()
+        .enumerate()
+        // The delimiter row
+        .filter(|(index, _)| *index != 1);
+
+    for (row, (index, line)) in rows.enumerate() {
+        let (line_row, offset) = match index {
+            0 => (start.0, start.1),
+            index => (start.0 + index, 0),
+        };
+
+        for (column, (cell_start, cell)) in split_row(line).into_iter().enumerate() {
+            let cell_start = (line_row, offset + cell_start);
+            let cell_end = (line_row, cell_start.1 + cell.len());
+            let kind = BlockKind::TableCell { row, column };
+
+            push(blocks, retained, kind, cell_start, cell_end, cell);
+        }
+    }
+}
+
+/// Splits a table row on its unescaped pipes, each cell is trimmed and comes with its byte offset
+/// in the line.
+fn split_row(line: &str) -> Vec<(usize, &str)> {
+    let mut bounds = vec![0];
+    let mut escaped = false;
+    for (index, c) in line.char_indices() {
+        match c {
+            '\\' => escaped = !escaped,
+            '|' if !escaped => {
+                bounds.push(index);
+                bounds.push(index + 1);
+            }
+            _ => escaped = false,
+        }
+    }
+    bounds.push(line.len());
+
+    let mut cells = bounds
+        .chunks(2)
+        .filter_map(|bounds| match bounds {
+            [start, end] => line.get(*start..*end).map(|cell| (*start, cell)),
+            _ => None,
+        })
+        .map(|(start, cell)| {
+            let leading = cell.len() - cell.trim_start().len();
+            (start + leading, cell.trim())
+        })
+        .collect::<Vec<_>>();
+
+    // The outer pipes are optional, they do not open cells of their own
+    if line.trim_start().starts_with('|') && !cells.is_empty() {
+        cells.remove(0);
+    }
+    if line.trim_end().ends_with('|') {
+        cells.pop();
+    }
+
+    cells
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::ensure;
+
+    use super::BlockKind;
+
+    #[test]
+    fn test_block_kinds() -> anyhow::Result<()> {
+        let code = [
+            "---",
+            "title: Notes",
+            "---",
+            "",
+            "## Setup ##",
+            "",
+            "- First item",
+            "  1. Nested step",
+            "",
+            "> Quoted text",
+            "",
+            "```rust",
+            "fn main() {}",
+            "```",
+            "",
+            "| Name | Value |",
+            "| ---- | ----- |",
+            "| a    | 1     |",
+            "",
+            "<div>html</div>",
+            "",
+        ]
+        .join("\n");
+
+        let mut parser = tree_sitter::Parser::new();
+        parser.set_language(tree_sitter_md::language())?;
+
+        let handle = super::super::Handle::new(&code, &mut parser)?;
+        let blocks = handle
+            .blocks
+            .read()
+            .map_err(|_| anyhow::anyhow!("Failed while reading the blocks"))?;
+
+        let kinds = blocks
+            .iter()
+            .map(|block| (block.kind.clone(), block.text.as_str()))
+            .collect::<Vec<_>>();
+
+        ensure!(
+            kinds
+                == [
+                    (BlockKind::Frontmatter, "title: Notes"),
+                    (BlockKind::Heading { level: 2 }, "Setup"),
+                    (
+                        BlockKind::ListItem {
+                            depth: 1,
+                            ordered: false
+                        },
+                        "First item"
+                    ),
+                    (
+                        BlockKind::ListItem {
+                            depth: 2,
+                            ordered: true
+                        },
+                        "Nested step"
+                    ),
+                    (BlockKind::BlockQuote { depth: 1 }, "Quoted text"),
+                    (
+                        BlockKind::Code {
+                            language: Some("rust".to_string())
+                        },
+                        "fn main() {}"
+                    ),
+                    (BlockKind::TableCell { row: 0, column: 0 }, "Name"),
+                    (BlockKind::TableCell { row: 0, column: 1 }, "Value"),
+                    (BlockKind::TableCell { row: 1, column: 0 }, "a"),
+                    (BlockKind::TableCell { row: 1, column: 1 }, "1"),
+                    (BlockKind::Html, "<div>html</div>"),
+                ]
+        );
+        ensure!(!blocks.iter().any(|block| {
+            matches!(block.kind, BlockKind::Code { .. }) && block.kind.policy().summary
+        }));
+
+        Ok(())
+    }
+}
@@ -0,0 +1,208 @@
+use std::collections::HashMap;
+
+use ropey::Rope;
+use tower_lsp::lsp_types;
+use tree_sitter::{InputEdit, Point};
+
+type Span = ((usize, usize), (usize, usize));
+
+/// Applies a content change to the rope and returns the edit that has to be replayed on the
+/// syntax tree before reparsing.
+///
+/// A change without a range replaces the entire document.
+pub(super) fn apply(
+    rope: &mut Rope,
+    change: &lsp_types::TextDocumentContentChangeEvent,
+) -> InputEdit {
+    let (start, end) = match change.range {
+        Some(range) => (char_index(rope, range.start), char_index(rope, range.end)),
+        None => (0, rope.len_chars()),
+    };
+    let end = end.max(start);
+
+    let start_byte = rope.char_to_byte(start);
+    let old_end_byte = rope.char_to_byte(end);
+    let start_position = point(rope, start);
+    let old_end_position = point(rope, end);
+
+    rope.remove(start..end);
+    rope.insert(start, &change.text);
+
+    let new_end = start + change.text.chars().count();
+
+    InputEdit {
+        start_byte,
+        old_end_byte,
+        new_end_byte: start_byte + change.text.len(),
+        start_position,
+        old_end_position,
+        new_end_position: point(rope, new_end),
+    }
+}
+
+/// Converts an LSP position (UTF-16 based) into a char index of the rope, clamping positions that
+/// point past the end of a line or the document.
+pub(super) fn char_index(rope: &Rope, position: lsp_types::Position) -> usize {
+    #[allow(clippy::as_conversions)]
+    let (line, character) = (position.line as usize, position.character as usize);
+
+    if line >= rope.len_lines() {
+        return rope.len_chars();
+    }
+
+    let line_start = rope.char_to_utf16_cu(rope.line_to_char(line));
+    let offset = character.min(line_len_utf16(rope.line(line)));
+
+    rope.utf16_cu_to_char(line_start + offset)
+}
+
+/// Converts a tree-sitter `(row, byte column)` point into an LSP position (UTF-16 based),
+/// columns past the end of the line clamp to its end.
+pub(super) fn position(rope: &Rope, (row, column): (usize, usize)) -> lsp_types::Position {
+    let character = match rope.get_line(row) {
+        Some(line) => {
+            let byte = column.min(line.len_bytes());
+            let char_idx = line.byte_to_char(byte);
+            line.char_to_utf16_cu(char_idx).min(line_len_utf16(line))
+        }
+        None => 0,
+    };
+
+    #[allow(clippy::as_conversions)]
+    lsp_types::Position {
+        line: row as u32,
+        character: character as u32,
+    }
+}
+
+/// Converts an LSP position (UTF-16 based) into a tree-sitter `(row, byte column)` point.
+pub(super) fn byte_point(rope: &Rope, position: lsp_types::Position) -> (usize, usize) {
+    let Point { row, column } = point(rope, char_index(rope, position));
+    (row, column)
+}
+
+/// Length of a line in UTF-16 code units, without its `\n`, `\r\n` or `\r` terminator.
+fn line_len_utf16(line: ropey::RopeSlice<'_>) -> usize {
+    let chars = line.len_chars();
+    let last = |n| chars.checked_sub(n).and_then(|index| line.get_char(index));
+
+    let terminator = match (last(2), last(1)) {
+        (Some('\r'), Some('\n')) => 2,
+        (_, Some('\n' | '\r')) => 1,
+        _ => 0,
+    };
+
+    line.len_utf16_cu() - terminator
+}
+
+fn point(rope: &Rope, char_idx: usize) -> Point {
+    let row = rope.char_to_line(char_idx);
+    let column = rope.char_to_byte(char_idx) - rope.line_to_byte(row);
+
+    Point { row, column }
+}
+
+/// Where `point` ends up after `edit`, points inside the replaced region collapse onto `inside`.
+fn map(point: (usize, usize), edit: &InputEdit, inside: Point) -> (usize, usize) {
+    let start = (edit.start_position.row, edit.start_position.column);
+    let old_end = (edit.old_end_position.row, edit.old_end_position.column);
+    let new_end = (edit.new_end_position.row, edit.new_end_position.column);
+
+    if point < start {
+        point
+    } else if point >= old_end {
+        let row = point.0 - old_end.0 + new_end.0;
+        let column = if point.0 == old_end.0 {
+            point.1 - old_end.1 + new_end.1
+        } else {
+            point.1
+        };
+        (row, column)
+    } else {
+        (inside.row, inside.column)
+    }
+}
+
+/// Blocks from the previous parse which survived the edits untouched, together with the regions
+/// of the new tree that have to be rebuilt.
+#[derive(Default)]
+pub(super) struct Retained {
+    blocks: HashMap<Span, super::Block>,
+    dirty: Vec<Span>,
+}
+
+impl Retained {
+    pub(super) fn new(
+        blocks: Vec<super::Block>,
+        edits: &[InputEdit],
+        changed: impl Iterator<Item = tree_sitter::Range>,
+    ) -> Self {
+        let mut retained = HashMap::with_capacity(blocks.len());
+
+        'blocks: for mut block in blocks {
+            for edit in edits {
+                let start = (edit.start_position.row, edit.start_position.column);
+                let old_end = (edit.old_end_position.row, edit.old_end_position.column);
+
+                if block.end < start {
+                    continue;
+                } else if block.start > old_end {
+                    block.start = map(block.start, edit, edit.new_end_position);
+                    block.end = map(block.end, edit, edit.new_end_position);
+                } else {
+                    continue 'blocks;
+                }
+            }
+
+            retained.insert((block.start, block.end), block);
+        }
+
+        let mut dirty: Vec<Span> = changed
+            .map(|range| {
+                (
+                    (range.start_point.row, range.start_point.column),
+                    (range.end_point.row, range.end_point.column),
+                )
+            })
+            .collect();
+
+        for (index, edit) in edits.iter().enumerate() {
+            let mut span = (
+                (edit.start_position.row, edit.start_position.column),
+                (edit.new_end_position.row, edit.new_end_position.column),
+            );
+
+            for later in edits.iter().skip(index + 1) {
+                span = (
+                    map(span.0, later, later.start_position),
+                    map(span.1, later, later.new_end_position),
+                );
+            }
+
+            dirty.push(span);
+        }
+
+        Self {
+            blocks: retained,
+            dirty,
+        }
+    }
+
+    /// Takes the previous block spanning exactly `start..end`, provided nothing in that span
+    /// changed.
+    pub(super) fn take(
+        &mut self,
+        start: (usize, usize),
+        end: (usize, usize),
+    ) -> Option<super::Block> {
+        if self
+            .dirty
+            .iter()
+            .any(|(dirty_start, dirty_end)| *dirty_start <= end && start <= *dirty_end)
+        {
+            return None;
+        }
+
+        self.blocks.remove(&(start, end))
+    }
+}
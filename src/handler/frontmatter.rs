@@ -0,0 +1,206 @@
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Yaml,
+    Toml,
+}
+
+/// The YAML or TOML metadata at the top of a document.
+#[derive(Debug, Clone)]
+pub struct Frontmatter {
+    pub format: Format,
+    /// Rows of the opening and closing delimiters.
+    pub span: (usize, usize),
+    /// Parsed values, an empty object when the frontmatter is empty or invalid.
+    pub data: serde_json::Value,
+    /// Why the frontmatter could not be parsed, if it could not.
+    pub error: Option<Error>,
+    /// Top level keys in order of appearance.
+    pub keys: Vec<Key>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Error {
+    pub message: String,
+    pub position: (usize, usize),
+}
+
+#[derive(Debug, Clone)]
+pub struct Key {
+    pub name: String,
+    pub start: (usize, usize),
+    pub end: (usize, usize),
+}
+
+impl Frontmatter {
+    pub fn key(&self, name: &str) -> Option<&Key> {
+        self.keys.iter().find(|key| key.name == name)
+    }
+
+    /// Whether `row` lies between the delimiters.
+    pub fn contains(&self, row: usize) -> bool {
+        self.span.0 < row && row < self.span.1
+    }
+}
+
+/// Rows spanned by the frontmatter, from the opening `---` (YAML) or `+++` (TOML) delimiter on
+/// the first line to its closing delimiter, both inclusive.
+///
+/// A leading `---` is also a thematic break, so YAML frontmatter running past a blank line is only
+/// accepted when its contents are a mapping.
+pub fn span(text: &str) -> Option<(usize, usize)> {
+    let mut lines = text.lines();
+    let delimiter = match lines.next()?.trim_end() {
+        "---" => "---",
+        "+++" => "+++",
+        _ => return None,
+    };
+
+    let index = lines.clone().position(|line| {
+        let line = line.trim_end();
+        line == delimiter || (delimiter == "---" && line == "...")
+    })?;
+
+    let inner = lines.take(index).collect::<Vec<_>>();
+    if delimiter == "---"
+        && inner.iter().any(|line| line.trim().is_empty())
+        && serde_yaml::from_str::<serde_yaml::Mapping>(&inner.join("\n")).is_err()
+    {
+        return None;
+    }
+
+    Some((0, index + 1))
+}
+
+/// Parses the frontmatter of the document, syntax errors are kept rather than returned so they
+/// can be reported.
+pub(super) fn parse(text: &str) -> Option<Frontmatter> {
+    let span = span(text)?;
+    let format = match text.starts_with("+++") {
+        true => Format::Toml,
+        false => Format::Yaml,
+    };
+
+    let inner = text
+        .lines()
+        .skip(1)
+        .take(span.1 - 1)
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    let parsed = match format {
+        Format::Yaml => serde_yaml::from_str::<serde_json::Value>(&inner).map_err(|e| Error {
+            position: e.location().map_or((1, 0), |location| {
+                (location.line(), location.column().saturating_sub(1))
+            }),
+            message: e.to_string(),
+        }),
+        Format::Toml => toml::from_str::<serde_json::Value>(&inner).map_err(|e| Error {
+            position: e
+                .span()
+                .map_or((1, 0), |span| offset_position(&inner, span.start)),
+            message: e.message().to_string(),
+        }),
+    };
+
+    let (data, error) = match parsed {
+        Ok(serde_json::Value::Null) => (serde_json::Value::Object(Default::default()), None),
+        Ok(data) => (data, None),
+        Err(error) => (serde_json::Value::Object(Default::default()), Some(error)),
+    };
+
+    Some(Frontmatter {
+        format,
+        span,
+        data,
+        error,
+        keys: keys(&inner, format),
+    })
+}
+
+/// Row, within the document, and column of a byte offset into the frontmatter contents.
+fn offset_position(inner: &str, offset: usize) -> (usize, usize) {
+    let before = inner.get(..offset).unwrap_or(inner);
+    match before.rsplit_once('\n') {
+        Some((lines, last)) => (lines.matches('\n').count() + 2, last.len()),
+        None => (1, before.len()),
+    }
+}
+
+/// Finds the top level keys line by line, the parsers do not keep track of positions.
+fn keys(inner: &str, format: Format) -> Vec<Key> {
+    let mut keys = Vec::new();
+
+    for (index, line) in inner.lines().enumerate() {
+        if line.starts_with(char::is_whitespace) || line.starts_with('#') {
+            continue;
+        }
+
+        let name = match format {
+            Format::Yaml if line.starts_with('-') => continue,
+            Format::Yaml => line.split_once(':').map(|(name, _)| name),
+            // Keys of tables belong to the table, not to the top level
+            Format::Toml if line.starts_with('[') => break,
+            Format::Toml => line.split_once('=').map(|(name, _)| name),
+        };
+        let Some(name) = name.map(str::trim_end).filter(|name| !name.is_empty()) else {
+            continue;
+        };
+
+        let row = index + 1;
+        keys.push(Key {
+            name: name.trim_matches(['"', '\'']).to_string(),
+            start: (row, 0),
+            end: (row, name.len()),
+        });
+    }
+
+    keys
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::ensure;
+
+    use super::Format;
+
+    #[test]
+    fn test_parse_frontmatter() -> anyhow::Result<()> {
+        let yaml = "---\ntitle: Notes\ntags:\n  - rust\n\"owner\": me\n---\n\n# Notes\n";
+        let frontmatter = super::parse(yaml).ok_or_else(|| anyhow::anyhow!("No frontmatter"))?;
+
+        ensure!(frontmatter.format == Format::Yaml);
+        ensure!(frontmatter.span == (0, 5));
+        ensure!(frontmatter.error.is_none());
+        ensure!(frontmatter.data.pointer("/tags/0") == Some(&"rust".into()));
+        ensure!(
+            frontmatter
+                .keys
+                .iter()
+                .map(|key| (key.name.as_str(), key.start, key.end))
+                .collect::<Vec<_>>()
+                == [
+                    ("title", (1, 0), (1, 5)),
+                    ("tags", (2, 0), (2, 4)),
+                    ("owner", (4, 0), (4, 7))
+                ]
+        );
+
+        let toml = "+++\ntitle = \"Notes\"\ndraft = yes\n+++\n";
+        let frontmatter = super::parse(toml).ok_or_else(|| anyhow::anyhow!("No frontmatter"))?;
+
+        ensure!(frontmatter.format == Format::Toml);
+        ensure!(frontmatter.error.as_ref().map(|error| error.position.0) == Some(2));
+
+        ensure!(super::parse("# No frontmatter\n").is_none());
+
+        // Thematic breaks around a paragraph, not frontmatter
+        let breaks = "---\n\nSome text.\n\n---\n";
+        ensure!(super::span(breaks).is_none());
+        ensure!(super::parse(breaks).is_none());
+
+        let spaced = "---\ntitle: Notes\n\ntags: [rust]\n---\n";
+        ensure!(super::span(spaced) == Some((0, 4)));
+
+        Ok(())
+    }
+}
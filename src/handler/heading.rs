@@ -0,0 +1,260 @@
+use std::collections::HashMap;
+
+#[derive(Debug, Clone)]
+pub struct Heading {
+    pub level: usize,
+    pub text: String,
+    /// GitHub style anchor of the heading, unique within the document.
+    pub slug: String,
+    pub start: (usize, usize),
+    pub end: (usize, usize),
+    /// Span of the heading text, without the markers.
+    pub content: ((usize, usize), (usize, usize)),
+}
+
+/// Where the section opened by each heading ends: at the next heading of the same or a higher
+/// level, or at `end` of the document.
+pub fn section_ends(headings: &[Heading], end: (usize, usize)) -> Vec<(usize, usize)> {
+    headings
+        .iter()
+        .enumerate()
+        .map(|(index, heading)| {
+            headings
+                .iter()
+                .skip(index + 1)
+                .find(|next| next.level <= heading.level)
+                .map_or(end, |next| next.start)
+        })
+        .collect()
+}
+
+/// A heading together with everything up to the end of its section, the document itself is the
+/// root of the tree.
+#[derive(Debug, Clone)]
+pub struct Section {
+    /// `None` for the document.
+    pub heading: Option<Heading>,
+    pub start: (usize, usize),
+    pub end: (usize, usize),
+    /// Sections of the headings one level down, in order.
+    pub children: Vec<Self>,
+}
+
+impl Section {
+    /// Number of sections nested in this one, at any depth.
+    pub fn descendants(&self) -> usize {
+        self.children
+            .iter()
+            .map(|child| 1 + child.descendants())
+            .sum()
+    }
+
+    /// The section opened by the heading starting on `row`.
+    pub fn find(&self, row: usize) -> Option<&Self> {
+        if self
+            .heading
+            .as_ref()
+            .is_some_and(|heading| heading.start.0 == row)
+        {
+            return Some(self);
+        }
+
+        self.children.iter().find_map(|child| child.find(row))
+    }
+}
+
+/// Nests every heading under the closest preceding heading of a lower level, the same outline as
+/// the document symbols.
+pub fn sections(headings: &[Heading], end: (usize, usize)) -> Section {
+    let mut root = Section {
+        heading: None,
+        start: (0, 0),
+        end,
+        children: Vec::new(),
+    };
+    // Sections still open, from the outermost to the innermost
+    let mut open: Vec<Section> = Vec::new();
+
+    let close = |open: &mut Vec<Section>, root: &mut Section| {
+        if let Some(section) = open.pop() {
+            match open.last_mut() {
+                Some(parent) => parent.children.push(section),
+                None => root.children.push(section),
+            }
+        }
+    };
+
+    for (heading, section_end) in headings.iter().zip(section_ends(headings, end)) {
+        while open
+            .last()
+            .and_then(|section| section.heading.as_ref())
+            .is_some_and(|parent| parent.level >= heading.level)
+        {
+            close(&mut open, &mut root);
+        }
+
+        open.push(Section {
+            heading: Some(heading.clone()),
+            start: heading.start,
+            end: section_end,
+            children: Vec::new(),
+        });
+    }
+
+    while !open.is_empty() {
+        close(&mut open, &mut root);
+    }
+
+    root
+}
+
+/// Collects the ATX and setext headings of the document in order.
+pub(super) fn collect(node: &tree_sitter::Node<'_>, text: &str) -> anyhow::Result<Vec<Heading>> {
+    let mut headings = Vec::new();
+    let mut slugs = HashMap::new();
+    let frontmatter = super::frontmatter::span(text).map(|(_, last)| last);
+    visit(node, text, frontmatter, &mut headings, &mut slugs)?;
+    Ok(headings)
+}
+
+fn visit(
+    node: &tree_sitter::Node<'_>,
+    text: &str,
+    frontmatter: Option<usize>,
+    headings: &mut Vec<Heading>,
+    slugs: &mut HashMap<String, usize>,
+) -> anyhow::Result<()> {
+    if node.kind() != "atx_heading" && node.kind() != "setext_heading" {
+        let mut cursor = node.walk();
+        for child in node.children(&mut cursor) {
+            visit(&child, text, frontmatter, headings, slugs)?;
+        }
+        return Ok(());
+    }
+
+    // The closing `---` of YAML frontmatter turns its last lines into a setext heading
+    if frontmatter.is_some_and(|last| node.start_position().row <= last) {
+        return Ok(());
+    }
+
+    let mut level = None;
+    let mut content = None;
+
+    let mut cursor = node.walk();
+    for child in node.children(&mut cursor) {
+        match child.kind() {
+            "atx_h1_marker" | "setext_h1_underline" => level = Some(1),
+            "atx_h2_marker" | "setext_h2_underline" => level = Some(2),
+            "atx_h3_marker" => level = Some(3),
+            "atx_h4_marker" => level = Some(4),
+            "atx_h5_marker" => level = Some(5),
+            "atx_h6_marker" => level = Some(6),
+            "heading_content" => content = Some(child),
+            _ => {}
+        }
+    }
+
+    let (Some(level), Some(content)) = (level, content) else {
+        return Ok(());
+    };
+
+    let raw = content.utf8_text(text.as_bytes())?;
+    let heading = strip_closing_sequence(raw.trim());
+    let leading = raw.len() - raw.trim_start().len();
+    let start = (
+        content.start_position().row,
+        content.start_position().column + leading,
+    );
+    let end = match heading.rsplit_once('\n') {
+        Some((before, last)) => (start.0 + before.matches('\n').count() + 1, last.len()),
+        None => (start.0, start.1 + heading.len()),
+    };
+
+    let slug = unique(slugify(heading), slugs);
+
+    headings.push(Heading {
+        level,
+        text: heading.to_string(),
+        slug,
+        start: (node.start_position().row, node.start_position().column),
+        end: (node.end_position().row, node.end_position().column),
+        content: (start, end),
+    });
+
+    Ok(())
+}
+
+/// Drops the optional closing `#` sequence of an ATX heading, e.g. `## Title ##`.
+pub(super) fn strip_closing_sequence(heading: &str) -> &str {
+    let stripped = heading.trim_end_matches('#');
+    if stripped.is_empty() || stripped.ends_with(char::is_whitespace) {
+        stripped.trim_end()
+    } else {
+        heading
+    }
+}
+
+/// Converts heading text into its anchor the way GitHub does: lowercase, punctuation dropped,
+/// spaces turned into hyphens.
+pub fn slugify(text: &str) -> String {
+    text.trim()
+        .to_lowercase()
+        .chars()
+        .filter_map(|c| match c {
+            ' ' => Some('-'),
+            c if c.is_alphanumeric() || c == '-' || c == '_' => Some(c),
+            _ => None,
+        })
+        .collect()
+}
+
+/// The anchors headings with the given texts get, in document order.
+pub fn slugs<'a>(texts: impl IntoIterator<Item = &'a str>) -> Vec<String> {
+    let mut slugs = HashMap::new();
+
+    texts
+        .into_iter()
+        .map(|text| unique(slugify(text), &mut slugs))
+        .collect()
+}
+
+fn unique(slug: String, slugs: &mut HashMap<String, usize>) -> String {
+    let count = slugs.entry(slug.clone()).or_insert(0);
+    let anchor = match *count {
+        0 => slug,
+        n => format!("{}-{}", slug, n),
+    };
+    *count += 1;
+    anchor
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::ensure;
+
+    #[test]
+    fn test_section_tree() -> anyhow::Result<()> {
+        let code = "# Guide\n\n## Install\n\n### Linux\n\n### macOS\n\n## Usage\n\n# Appendix\n";
+
+        let mut parser = tree_sitter::Parser::new();
+        parser.set_language(tree_sitter_md::language())?;
+        let tree = parser
+            .parse(code, None)
+            .ok_or_else(|| anyhow::anyhow!("Failed to parse"))?;
+
+        let headings = super::collect(&tree.root_node(), code)?;
+        let root = super::sections(&headings, (11, 0));
+
+        ensure!(root.children.len() == 2);
+        ensure!(root.descendants() == 6);
+
+        let install = root
+            .find(2)
+            .ok_or_else(|| anyhow::anyhow!("No section on row 2"))?;
+        ensure!(install.children.len() == 2);
+        ensure!(install.end == (8, 0));
+        ensure!(root.find(1).is_none());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,315 @@
+use tower_lsp::lsp_types;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kind {
+    /// `[text](destination)` and `![alt](destination)`.
+    Inline,
+    /// `[text][label]`, `[label][]` and `[label]`.
+    Reference,
+    /// `[label]: destination`.
+    Definition,
+    /// `[^label]`.
+    Footnote,
+    /// `[^label]: text`.
+    FootnoteDefinition,
+}
+
+#[derive(Debug, Clone)]
+pub struct Link {
+    pub kind: Kind,
+    /// Normalized label of reference links, definitions and footnotes, without the `^`.
+    pub label: Option<String>,
+    pub destination: Option<Destination>,
+    pub start: (usize, usize),
+    pub end: (usize, usize),
+}
+
+#[derive(Debug, Clone)]
+pub struct Destination {
+    /// The destination as written, without the optional angle brackets.
+    pub text: String,
+    pub start: (usize, usize),
+    pub end: (usize, usize),
+}
+
+impl Destination {
+    /// The part before the `#`, empty for links within the same document.
+    pub fn path(&self) -> &str {
+        self.text
+            .split_once('#')
+            .map_or(self.text.as_str(), |(path, _)| path)
+    }
+
+    pub fn anchor(&self) -> Option<&str> {
+        self.text.split_once('#').map(|(_, anchor)| anchor)
+    }
+
+    /// The heading slug the anchor refers to, anchors match regardless of case like on GitHub.
+    pub fn slug(&self) -> Option<String> {
+        self.anchor().map(str::to_lowercase)
+    }
+
+    /// Whether the destination carries a scheme (`https:`, `mailto:`), i.e. points outside of
+    /// the workspace.
+    pub fn is_external(&self) -> bool {
+        self.path().split_once(':').is_some_and(|(scheme, _)| {
+            !scheme.is_empty()
+                && scheme
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || matches!(c, '+' | '-' | '.'))
+        })
+    }
+
+    /// The document the destination points at, relative to the document at `base`.
+    pub fn resolve(&self, base: &lsp_types::Url) -> Option<lsp_types::Url> {
+        if self.is_external() {
+            return None;
+        }
+
+        resolve(base, self.path())
+    }
+}
+
+/// Resolves a relative path against the document at `base`, an empty path is the document
+/// itself.
+pub fn resolve(base: &lsp_types::Url, path: &str) -> Option<lsp_types::Url> {
+    let mut target = match path {
+        "" => base.clone(),
+        path => base.join(path).ok()?,
+    };
+    target.set_fragment(None);
+    target.set_query(None);
+
+    Some(target)
+}
+
+/// Reference labels match case-insensitively and regardless of inner whitespace.
+pub fn normalize(label: &str) -> String {
+    label
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .to_lowercase()
+}
+
+/// Collects the links, link reference definitions and footnotes of the document in order.
+pub(super) fn collect(node: &tree_sitter::Node<'_>, text: &str) -> anyhow::Result<Vec<Link>> {
+    let mut links = Vec::new();
+    visit(node, text, &mut links)?;
+
+    // Brackets in frontmatter values are not links
+    if let Some((_, last)) = super::frontmatter::span(text) {
+        links.retain(|link| link.start.0 > last);
+    }
+
+    Ok(links)
+}
+
+fn visit(node: &tree_sitter::Node<'_>, text: &str, links: &mut Vec<Link>) -> anyhow::Result<()> {
+    let start = (node.start_position().row, node.start_position().column);
+    let end = (node.end_position().row, node.end_position().column);
+
+    let link = match node.kind() {
+        "inline_link" | "image" => Some(Link {
+            kind: Kind::Inline,
+            label: None,
+            destination: destination(node, text)?,
+            start,
+            end,
+        }),
+        "full_reference_link" | "collapsed_reference_link" | "shortcut_link" => {
+            let label = match node.kind() {
+                "full_reference_link" => child(node, "link_label")
+                    .map(|label| label.utf8_text(text.as_bytes()))
+                    .transpose()?
+                    .map(strip_brackets),
+                _ => child(node, "link_text")
+                    .map(|label| label.utf8_text(text.as_bytes()))
+                    .transpose()?,
+            };
+
+            label.map(|label| match label.strip_prefix('^') {
+                // `[^1]: A note` parses as a paragraph starting with the shortcut link
+                Some(footnote)
+                    if node.prev_sibling().is_none()
+                        && text.as_bytes().get(node.end_byte()) == Some(&b':') =>
+                {
+                    Link {
+                        kind: Kind::FootnoteDefinition,
+                        label: Some(normalize(footnote)),
+                        destination: None,
+                        start,
+                        end: node.parent().map_or(end, |parent| {
+                            (parent.end_position().row, parent.end_position().column)
+                        }),
+                    }
+                }
+                Some(footnote) => Link {
+                    kind: Kind::Footnote,
+                    label: Some(normalize(footnote)),
+                    destination: None,
+                    start,
+                    end,
+                },
+                None => Link {
+                    kind: Kind::Reference,
+                    label: Some(normalize(label)),
+                    destination: None,
+                    start,
+                    end,
+                },
+            })
+        }
+        "link_reference_definition" => {
+            let label = child(node, "link_label")
+                .map(|label| label.utf8_text(text.as_bytes()))
+                .transpose()?
+                .map(strip_brackets);
+
+            match label {
+                // The grammar has no footnotes, `[^1]: note` parses as a reference definition
+                // whose "destination" is the note.
+                Some(label) if label.starts_with('^') => Some(Link {
+                    kind: Kind::FootnoteDefinition,
+                    label: Some(normalize(label.trim_start_matches('^'))),
+                    destination: None,
+                    start,
+                    end,
+                }),
+                Some(label) => Some(Link {
+                    kind: Kind::Definition,
+                    label: Some(normalize(label)),
+                    destination: destination(node, text)?,
+                    start,
+                    end,
+                }),
+                None => None,
+            }
+        }
+        _ => None,
+    };
+
+    match link {
+        Some(link) => links.push(link),
+        None => {
+            let mut cursor = node.walk();
+            for child in node.children(&mut cursor) {
+                visit(&child, text, links)?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn destination(node: &tree_sitter::Node<'_>, text: &str) -> anyhow::Result<Option<Destination>> {
+    let Some(destination) = child(node, "link_destination") else {
+        return Ok(None);
+    };
+
+    let raw = destination.utf8_text(text.as_bytes())?;
+    let start = (
+        destination.start_position().row,
+        destination.start_position().column,
+    );
+    let end = (
+        destination.end_position().row,
+        destination.end_position().column,
+    );
+
+    let destination = match raw.strip_prefix('<').and_then(|raw| raw.strip_suffix('>')) {
+        Some(inner) => Destination {
+            text: inner.to_string(),
+            start: (start.0, start.1 + 1),
+            end: (end.0, end.1.saturating_sub(1)),
+        },
+        None => Destination {
+            text: raw.to_string(),
+            start,
+            end,
+        },
+    };
+
+    Ok(Some(destination))
+}
+
+fn child<'tree>(node: &tree_sitter::Node<'tree>, kind: &str) -> Option<tree_sitter::Node<'tree>> {
+    let mut cursor = node.walk();
+    node.children(&mut cursor)
+        .find(|child| child.kind() == kind)
+}
+
+fn strip_brackets(label: &str) -> &str {
+    label
+        .strip_prefix('[')
+        .and_then(|label| label.strip_suffix(']'))
+        .unwrap_or(label)
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::ensure;
+
+    use super::Kind;
+
+    #[allow(clippy::indexing_slicing)]
+    #[test]
+    fn test_collect_links() -> anyhow::Result<()> {
+        let code = [
+            "See [a](./other.md#Section) and [b](<x y.md>), [c][Some  Ref] and [^1].",
+            "",
+            "[some ref]: https://example.com",
+            "[^1]: A note",
+            "",
+        ]
+        .join("\n");
+
+        let mut parser = tree_sitter::Parser::new();
+        parser.set_language(tree_sitter_md::language())?;
+        let tree = parser
+            .parse(&code, None)
+            .ok_or_else(|| anyhow::anyhow!("Failed to parse the text with the parser"))?;
+
+        let links = super::collect(&tree.root_node(), &code)?;
+        let kinds = links.iter().map(|link| link.kind).collect::<Vec<_>>();
+
+        ensure!(
+            kinds
+                == [
+                    Kind::Inline,
+                    Kind::Inline,
+                    Kind::Reference,
+                    Kind::Footnote,
+                    Kind::Definition,
+                    Kind::FootnoteDefinition,
+                ]
+        );
+
+        let destination = links[0]
+            .destination
+            .as_ref()
+            .ok_or_else(|| anyhow::anyhow!("Missing destination"))?;
+        ensure!(destination.path() == "./other.md");
+        ensure!(destination.anchor() == Some("Section"));
+        ensure!(destination.start == (0, 8));
+
+        let destination = links[1]
+            .destination
+            .as_ref()
+            .ok_or_else(|| anyhow::anyhow!("Missing destination"))?;
+        ensure!(destination.text == "x y.md");
+        ensure!(destination.start == (0, 37));
+
+        ensure!(links[2].label.as_deref() == Some("some ref"));
+        ensure!(links[4].label.as_deref() == Some("some ref"));
+        ensure!(
+            links[4]
+                .destination
+                .as_ref()
+                .is_some_and(|destination| destination.is_external())
+        );
+        ensure!(links[5].label.as_deref() == Some("1"));
+
+        Ok(())
+    }
+}
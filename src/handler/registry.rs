@@ -1,17 +1,82 @@
-use std::{collections::HashSet, sync::Arc};
+use std::{
+    collections::HashSet,
+    sync::{
+        Arc, Mutex, OnceLock,
+        atomic::{AtomicUsize, Ordering},
+    },
+};
 
 use dashmap::DashMap;
+use tower_lsp::lsp_types;
 
-use rust_bert::pipelines::keywords_extraction::KeywordExtractionModel;
+pub mod cache;
+mod index;
+mod worker;
 
 const SUMMARY_THRESHOLD: usize = 100;
 
+/// A document and the start of a block within it.
+pub type Location = (lsp_types::Url, (usize, usize));
+
+/// Snapshot of the background model work, reported by `marky/status`.
+#[derive(Debug, Clone, Default, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Status {
+    pub queue: Queue,
+    pub models: Models,
+    /// `None` when the on-disk cache is disabled.
+    pub cache: Option<cache::Stats>,
+}
+
+/// Blocks waiting for, or being handled by, each kind of analysis.
+#[derive(Debug, Clone, Copy, Default, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Queue {
+    pub keywords: usize,
+    pub summaries: usize,
+    pub embeddings: usize,
+}
+
+impl Queue {
+    pub fn total(&self) -> usize {
+        self.keywords + self.summaries + self.embeddings
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Models {
+    pub keywords: Load,
+    pub summarization: Load,
+}
+
+/// Models are only loaded once there is work for them.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum Load {
+    #[default]
+    Unloaded,
+    Loading,
+    Loaded,
+    Failed,
+}
+
 #[derive(Clone)]
 pub struct Registry {
     keyword_registry: Arc<DashMap<[u8; 32], Vec<String>>>, // blake3 hash
     reverse_index: Arc<DashMap<[u8; 32], super::Block>>,
     embedding_registry: Arc<DashMap<[u8; 32], Vec<f32>>>,
     summary_registry: Arc<DashMap<[u8; 32], String>>,
+    vector_index: Arc<Mutex<index::VectorIndex>>,
+    /// Where the blocks of each analyzed document start, so search hits are found without
+    /// walking the workspace.
+    locations: Arc<DashMap<[u8; 32], Vec<Location>>>,
+    /// Hashes placed for each document, to take them out again when it is analyzed anew.
+    placed: Arc<DashMap<lsp_types::Url, HashSet<[u8; 32]>>>,
+    cache: Arc<OnceLock<cache::Cache>>,
+    worker: Arc<Mutex<Option<worker::Worker>>>,
+    /// Blocks currently sent to the embedding engine.
+    embedding_pending: Arc<AtomicUsize>,
 }
 
 impl Registry {
@@ -21,137 +86,297 @@ impl Registry {
             reverse_index: Arc::new(DashMap::new()),
             embedding_registry: Arc::new(DashMap::new()),
             summary_registry: Arc::new(DashMap::new()),
+            vector_index: Arc::new(Mutex::new(index::VectorIndex::default())),
+            locations: Arc::new(DashMap::new()),
+            placed: Arc::new(DashMap::new()),
+            cache: Arc::new(OnceLock::new()),
+            worker: Arc::new(Mutex::new(None)),
+            embedding_pending: Arc::new(AtomicUsize::new(0)),
         }
     }
 
-    pub fn keyword_text(&self, content: &[super::Block]) -> anyhow::Result<()> {
-        let model = KeywordExtractionModel::new(Default::default())?;
+    /// Backs the registry with an on-disk cache, values found there are not computed again.
+    pub fn attach(&self, cache: cache::Cache) {
+        let _ = self.cache.set(cache);
+    }
 
+    /// Records where the blocks of a document start, replacing its previous blocks. Returns the
+    /// hashes of the previous blocks no longer found in any document.
+    pub fn place(&self, uri: &lsp_types::Url, blocks: &[super::Block]) -> HashSet<[u8; 32]> {
+        let previous = self
+            .placed
+            .remove(uri)
+            .map(|(_, previous)| previous)
+            .unwrap_or_default();
+        for hash in &previous {
+            self.locations.remove_if_mut(hash, |_, locations| {
+                locations.retain(|(other, _)| other != uri);
+                locations.is_empty()
+            });
+        }
+
+        let mut hashes = HashSet::with_capacity(blocks.len());
+        for block in blocks {
+            hashes.insert(block.metadata.hash);
+            self.locations
+                .entry(block.metadata.hash)
+                .or_default()
+                .push((uri.clone(), block.start));
+        }
+        self.placed.insert(uri.clone(), hashes);
+
+        previous
+            .into_iter()
+            .filter(|hash| !self.locations.contains_key(hash))
+            .collect()
+    }
+
+    /// Documents and starts of the blocks with the given hash, as of their last analysis.
+    pub fn locations(&self, hash: &[u8; 32]) -> Vec<Location> {
+        self.locations
+            .get(hash)
+            .map(|locations| locations.clone())
+            .unwrap_or_default()
+    }
+
+    /// Queues keyword extraction for the blocks missing from the registry, values found in the
+    /// cache are restored right away.
+    pub fn keyword_text(&self, content: &[super::Block]) -> anyhow::Result<()> {
         for block in content {
             self.reverse_index
                 .insert(block.metadata.hash, block.clone());
 
-            if self.keyword_registry.contains_key(&block.metadata.hash) {
+            if self.keyword_registry.contains_key(&block.metadata.hash)
+                || !block.kind.policy().keywords
+            {
                 continue;
-            } else {
-                self.keyword_registry
-                    .insert(block.metadata.hash, self.generate_keywords(block, &model)?);
+            }
+
+            let cached = self
+                .cache
+                .get()
+                .and_then(|cache| cache.keywords.get(&block.metadata.hash));
+
+            match cached {
+                Some(keywords) => {
+                    self.keyword_registry.insert(block.metadata.hash, keywords);
+                }
+                None => self.submit(worker::Task::Keywords, block)?,
             }
         }
 
         Ok(())
     }
 
+    /// Queues summarization for the long blocks missing from the registry, values found in the
+    /// cache are restored right away.
     pub fn summarize_text(&self, content: &[super::Block]) -> anyhow::Result<()> {
-        let summary_model =
-            rust_bert::pipelines::summarization::SummarizationModel::new(Default::default())?;
-
         for block in content {
             self.reverse_index
                 .insert(block.metadata.hash, block.clone());
 
             if self.summary_registry.contains_key(&block.metadata.hash)
+                || !block.kind.policy().summary
                 || block.stat.words < SUMMARY_THRESHOLD
             {
                 continue;
-            } else {
-                let summary = summary_model.summarize(&[block.text.clone()])?;
-                if let Some(summary) = summary.first() {
-                    self.summary_registry
-                        .insert(block.metadata.hash, summary.clone());
+            }
+
+            let cached = self
+                .cache
+                .get()
+                .and_then(|cache| cache.summaries.get(&block.metadata.hash));
+
+            match cached {
+                Some(summary) => {
+                    self.summary_registry.insert(block.metadata.hash, summary);
                 }
+                None => self.submit(worker::Task::Summary, block)?,
             }
         }
 
         Ok(())
     }
 
+    /// Drops the queued keyword and summary work of blocks that are no longer needed.
+    pub fn cancel(&self, hashes: &HashSet<[u8; 32]>) -> anyhow::Result<()> {
+        match &*self
+            .worker
+            .lock()
+            .map_err(|_| anyhow::anyhow!("Failed while locking the model worker"))?
+        {
+            Some(worker) => worker.cancel(hashes),
+            None => Ok(()),
+        }
+    }
+
+    /// Has the models handle the queued work of a block before anything else.
+    pub fn prioritize(&self, hash: &[u8; 32]) -> anyhow::Result<()> {
+        match &*self
+            .worker
+            .lock()
+            .map_err(|_| anyhow::anyhow!("Failed while locking the model worker"))?
+        {
+            Some(worker) => worker.prioritize(hash),
+            None => Ok(()),
+        }
+    }
+
+    /// Queue depth, model load state and cache hit rates.
+    pub fn status(&self) -> anyhow::Result<Status> {
+        let (queue, models) = match &*self
+            .worker
+            .lock()
+            .map_err(|_| anyhow::anyhow!("Failed while locking the model worker"))?
+        {
+            Some(worker) => worker.status()?,
+            None => Default::default(),
+        };
+
+        Ok(Status {
+            queue: Queue {
+                embeddings: self.embedding_pending.load(Ordering::Relaxed),
+                ..queue
+            },
+            models,
+            cache: self.cache.get().map(cache::Cache::stats),
+        })
+    }
+
+    /// Whether keywords of the block are queued but not extracted yet.
+    pub fn keywords_pending(&self, content: &super::Block) -> bool {
+        self.pending(worker::Task::Keywords, &content.metadata.hash)
+    }
+
+    /// Whether the block is queued for summarization but not summarized yet.
+    pub fn summary_pending(&self, content: &super::Block) -> bool {
+        self.pending(worker::Task::Summary, &content.metadata.hash)
+    }
+
+    /// Failures of the model worker since the last call, for the caller to log.
+    pub fn take_errors(&self) -> Vec<String> {
+        match self.worker.lock().as_deref() {
+            Ok(Some(worker)) => worker.take_errors(),
+            _ => vec![],
+        }
+    }
+
+    fn pending(&self, task: worker::Task, hash: &[u8; 32]) -> bool {
+        match self.worker.lock().as_deref() {
+            Ok(Some(worker)) => worker.pending(task, hash),
+            _ => false,
+        }
+    }
+
+    /// Hands a block to the model worker, starting it on first use.
+    fn submit(&self, task: worker::Task, block: &super::Block) -> anyhow::Result<()> {
+        let mut worker = self
+            .worker
+            .lock()
+            .map_err(|_| anyhow::anyhow!("Failed while locking the model worker"))?;
+
+        let worker = match &mut *worker {
+            Some(worker) => worker,
+            None => worker.insert(worker::Worker::spawn(worker::Sink {
+                keywords: self.keyword_registry.clone(),
+                summaries: self.summary_registry.clone(),
+                cache: self.cache.clone(),
+            })?),
+        };
+
+        worker.submit(task, block)
+    }
+
     pub async fn embed_text(
         &self,
         content: &[super::Block],
         engine: &dyn crate::embedding::Embedding,
     ) -> anyhow::Result<()> {
+        // Code and markup do not embed meaningfully with a text model
+        let content = content
+            .iter()
+            .filter(|block| block.kind.policy().embedding)
+            .collect::<Vec<_>>();
+
+        let mut restored = false;
+        if let Some(cache) = self.cache.get() {
+            for block in &content {
+                if self.embedding_registry.contains_key(&block.metadata.hash) {
+                    continue;
+                }
+                if let Some(embedding) = cache.embeddings.get(&block.metadata.hash) {
+                    self.embedding_registry
+                        .insert(block.metadata.hash, embedding);
+                    restored = true;
+                }
+            }
+        }
+
         let (key_list, text_list): (Vec<_>, Vec<_>) = content
             .iter()
             .filter(|x| !self.embedding_registry.contains_key(&x.metadata.hash))
             .map(|data| (data.metadata.hash, data.text.clone()))
             .unzip();
 
+        if key_list.is_empty() {
+            if restored {
+                self.invalidate_index();
+            }
+            return Ok(());
+        }
+
+        let pending = Pending::new(&self.embedding_pending, key_list.len());
         let embeddings = engine.embed_multiple(text_list).await?;
+        drop(pending);
 
-        for (key, embedding) in key_list.into_iter().zip(embeddings.into_iter()) {
+        for (key, embedding) in key_list.into_iter().zip(embeddings) {
+            if let Some(cache) = self.cache.get() {
+                cache.embeddings.put(key, &embedding)?;
+            }
             self.embedding_registry.insert(key, embedding);
         }
 
+        self.invalidate_index();
+
         Ok(())
     }
 
-    // WARNING: This is an optimization to perform garbage collection, please reconsider this
-    // when we start indexing multiple files
-    //
-    // The approach assumes that everything that exists in the project, will be present in the
-    // reverse index, this assumption is used to remove redundant entries in the keyword registry.
-    pub fn garbage_collect(&self) -> anyhow::Result<()> {
-        let true_keys = self
-            .reverse_index
-            .iter()
-            .map(|x| *x.key())
-            .collect::<HashSet<_>>();
-
-        let k_keys = self
-            .keyword_registry
-            .iter()
-            .map(|x| *x.key())
-            .collect::<HashSet<_>>();
-
-        let garbage_keys = k_keys.difference(&true_keys).cloned();
-
-        for key in garbage_keys {
-            self.keyword_registry.remove(&key);
-        }
+    /// Drops every entry whose hash is not in `live`, i.e. blocks which are no longer part of any
+    /// tracked document.
+    pub fn garbage_collect(&self, live: &HashSet<[u8; 32]>) -> anyhow::Result<()> {
+        self.reverse_index.retain(|key, _| live.contains(key));
+        self.keyword_registry.retain(|key, _| live.contains(key));
+        self.embedding_registry.retain(|key, _| live.contains(key));
+        self.summary_registry.retain(|key, _| live.contains(key));
+        self.locations.retain(|key, _| live.contains(key));
 
-        let e_keys = self
-            .embedding_registry
-            .iter()
-            .map(|x| *x.key())
-            .collect::<HashSet<_>>();
-
-        let garbage_keys2 = e_keys.difference(&true_keys).cloned();
-
-        for key in garbage_keys2 {
-            self.embedding_registry.remove(&key);
-        }
-
-        let s_keys = self
-            .summary_registry
-            .iter()
-            .map(|x| *x.key())
-            .collect::<HashSet<_>>();
-
-        let garbage_keys3 = s_keys.difference(&true_keys).cloned();
-
-        for key in garbage_keys3 {
-            self.summary_registry.remove(&key);
-        }
+        self.invalidate_index();
 
         Ok(())
     }
 
-    fn generate_keywords(
-        &self,
-        content: &super::Block,
-        model: &KeywordExtractionModel<'_>,
-    ) -> anyhow::Result<Vec<String>> {
-        let text = content.text.clone();
-        let output = model
-            .predict(&[text])?
-            .first()
-            .ok_or_else(|| anyhow::anyhow!("No keywords found"))?
-            .iter()
-            .filter(|x| x.score > 0.4)
-            .map(|x| x.text.clone())
-            .collect();
-        Ok(output)
+    /// Returns the hashes of the `k` embedded blocks closest to `query`, together with their
+    /// cosine similarity, best match first.
+    ///
+    /// Searching may rebuild the index, which runs on the blocking pool.
+    pub async fn nearest(&self, query: &[f32], k: usize) -> anyhow::Result<Vec<([u8; 32], f32)>> {
+        let index = self.vector_index.clone();
+        let embeddings = self.embedding_registry.clone();
+        let query = query.to_vec();
+
+        tokio::task::spawn_blocking(move || {
+            index
+                .lock()
+                .map_err(|_| anyhow::anyhow!("Failed while locking the vector index"))?
+                .search(&embeddings, &query, k)
+        })
+        .await?
+    }
+
+    fn invalidate_index(&self) {
+        if let Ok(mut index) = self.vector_index.lock() {
+            index.invalidate();
+        }
     }
 
     pub fn get_keywords(&self, content: &super::Block) -> Option<Vec<String>> {
@@ -167,6 +392,26 @@ impl Registry {
         None
     }
 
+    pub fn get_embedding(&self, content: &super::Block) -> Option<Vec<f32>> {
+        self.embedding_registry
+            .get(&content.metadata.hash)
+            .map(|embedding| embedding.clone())
+    }
+
+    /// Cosine similarity between the embedding stored for `hash` and `query`.
+    pub fn similarity(&self, hash: &[u8; 32], query: &[f32]) -> Option<f32> {
+        let embedding = self.embedding_registry.get(hash)?;
+        if embedding.len() != query.len() {
+            return None;
+        }
+
+        let dot = embedding.iter().zip(query).map(|(a, b)| a * b).sum::<f32>();
+        let norm = embedding.iter().map(|a| a * a).sum::<f32>().sqrt()
+            * query.iter().map(|b| b * b).sum::<f32>().sqrt();
+
+        (norm > 0.0).then(|| dot / norm)
+    }
+
     pub fn get_summary(&self, content: &super::Block) -> Option<String> {
         if let Some(summary) = self.summary_registry.get(&content.metadata.hash) {
             let value = summary.clone();
@@ -181,8 +426,70 @@ impl Registry {
     }
 }
 
+/// Counts blocks as in flight for as long as it lives, analyses can be aborted at any await.
+struct Pending<'a> {
+    counter: &'a AtomicUsize,
+    count: usize,
+}
+
+impl<'a> Pending<'a> {
+    fn new(counter: &'a AtomicUsize, count: usize) -> Self {
+        counter.fetch_add(count, Ordering::Relaxed);
+        Self { counter, count }
+    }
+}
+
+impl Drop for Pending<'_> {
+    fn drop(&mut self) {
+        self.counter.fetch_sub(self.count, Ordering::Relaxed);
+    }
+}
+
 impl Default for Registry {
     fn default() -> Self {
         Self::new()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use anyhow::ensure;
+    use tower_lsp::lsp_types;
+
+    #[test]
+    fn test_place_blocks() -> anyhow::Result<()> {
+        let mut parser = tree_sitter::Parser::new();
+        parser.set_language(tree_sitter_md::language())?;
+
+        let registry = super::Registry::new();
+        let uri = lsp_types::Url::parse("file:///notes/a.md")?;
+
+        let before = super::super::Handle::new("First.\n\nSecond.\n", &mut parser)?;
+        let blocks = before
+            .blocks
+            .read()
+            .map_err(|_| anyhow::anyhow!("Poisoned"))?
+            .clone();
+        registry.place(&uri, &blocks);
+
+        let after = super::super::Handle::new("Second.\n", &mut parser)?;
+        let moved = after
+            .blocks
+            .read()
+            .map_err(|_| anyhow::anyhow!("Poisoned"))?
+            .clone();
+        registry.place(&uri, &moved);
+
+        for block in &blocks {
+            let locations = registry.locations(&block.metadata.hash);
+            let expected = moved
+                .iter()
+                .filter(|other| other.metadata.hash == block.metadata.hash)
+                .map(|other| (uri.clone(), other.start))
+                .collect::<Vec<_>>();
+            ensure!(locations == expected, "{:?}", locations);
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,374 @@
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{BufRead, BufReader, BufWriter, Seek, SeekFrom, Write},
+    marker::PhantomData,
+    path::{Path, PathBuf},
+    sync::{
+        Mutex,
+        atomic::{AtomicU64, Ordering},
+    },
+};
+
+use dashmap::DashMap;
+
+/// Identifies the rust-bert pipelines, bump them when the default models change.
+const KEYWORD_MODEL: &str = "rust-bert-0.23/keyword-extraction";
+const SUMMARY_MODEL: &str = "rust-bert-0.23/summarization";
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct Config {
+    pub enabled: bool,
+    /// Defaults to `marky` in the user cache directory, e.g. `~/.cache/marky` on Linux.
+    pub directory: Option<PathBuf>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            directory: None,
+        }
+    }
+}
+
+/// On-disk copy of the registry, so keywords, summaries and embeddings survive restarts.
+///
+/// Every table is an append-only JSON lines file per model, keyed by the block hash. Only the
+/// offsets of the records are kept in memory, values are read back when the registry misses them.
+pub struct Cache {
+    pub(super) keywords: Table<Vec<String>>,
+    pub(super) summaries: Table<String>,
+    pub(super) embeddings: Table<Vec<f32>>,
+}
+
+impl Cache {
+    /// Opens the cache, `None` when it is disabled.
+    pub fn open(config: &Config, embedding_model: &str) -> anyhow::Result<Option<Self>> {
+        if !config.enabled {
+            return Ok(None);
+        }
+
+        let directory = match &config.directory {
+            Some(directory) => directory.clone(),
+            None => dirs::cache_dir()
+                .ok_or_else(|| anyhow::anyhow!("No cache directory for the current user"))?
+                .join("marky"),
+        };
+        fs::create_dir_all(&directory)?;
+
+        Ok(Some(Self {
+            keywords: Table::open(&directory, "keywords", KEYWORD_MODEL)?,
+            summaries: Table::open(&directory, "summaries", SUMMARY_MODEL)?,
+            embeddings: Table::open(&directory, "embeddings", embedding_model)?,
+        }))
+    }
+
+    pub(super) fn stats(&self) -> Stats {
+        Stats {
+            keywords: self.keywords.hit_rate(),
+            summaries: self.summaries.hit_rate(),
+            embeddings: self.embeddings.hit_rate(),
+        }
+    }
+}
+
+/// Lookups of values missing from memory since the server started, per table.
+#[derive(Debug, Clone, Copy, Default, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Stats {
+    pub keywords: HitRate,
+    pub summaries: HitRate,
+    pub embeddings: HitRate,
+}
+
+#[derive(Debug, Clone, Copy, Default, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HitRate {
+    pub hits: u64,
+    pub lookups: u64,
+    /// Between 0 and 1, 0 before the first lookup.
+    pub rate: f64,
+}
+
+#[derive(serde::Serialize, serde::Deserialize)]
+struct Record<V> {
+    hash: String,
+    model: String,
+    value: V,
+}
+
+pub(super) struct Table<V> {
+    model: String,
+    path: PathBuf,
+    /// Advisory lock shared with other instances using the same cache: exclusive while the file
+    /// is appended to or compacted, shared while records are read.
+    lock: Mutex<File>,
+    /// Offset of the record of each hash in the file.
+    offsets: DashMap<[u8; 32], u64>,
+    hits: AtomicU64,
+    lookups: AtomicU64,
+    value: PhantomData<V>,
+}
+
+impl<V> Table<V>
+where
+    V: Clone + serde::Serialize + serde::de::DeserializeOwned,
+{
+    /// Opens the table of `model`, models get a file of their own so switching between them, or
+    /// instances configured with different ones, keeps the values of each.
+    fn open(directory: &Path, name: &str, model: &str) -> anyhow::Result<Self> {
+        let id = blake3::hash(model.as_bytes()).to_hex();
+        let path = directory.join(format!("{}-{}.jsonl", name, id.get(..16).unwrap_or(&id)));
+        let lock = OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(path.with_extension("lock"))?;
+
+        let table = Self {
+            model: model.to_string(),
+            path,
+            lock: Mutex::new(lock),
+            offsets: DashMap::new(),
+            hits: AtomicU64::new(0),
+            lookups: AtomicU64::new(0),
+            value: PhantomData,
+        };
+
+        let lock = table.lock()?;
+        lock.lock()?;
+        let loaded = table.load().and_then(|stale| match stale {
+            0 => Ok(()),
+            _ => table.compact(),
+        });
+        lock.unlock()?;
+        drop(lock);
+        loaded?;
+
+        Ok(table)
+    }
+
+    pub(super) fn get(&self, hash: &[u8; 32]) -> Option<V> {
+        let value = self.read(hash).ok().flatten();
+
+        self.lookups.fetch_add(1, Ordering::Relaxed);
+        if value.is_some() {
+            self.hits.fetch_add(1, Ordering::Relaxed);
+        }
+
+        value
+    }
+
+    pub(super) fn put(&self, hash: [u8; 32], value: &V) -> anyhow::Result<()> {
+        if self.offsets.contains_key(&hash) {
+            return Ok(());
+        }
+
+        let line = serde_json::to_string(&Record {
+            hash: blake3::Hash::from(hash).to_hex().to_string(),
+            model: self.model.clone(),
+            value,
+        })?;
+
+        let lock = self.lock()?;
+        lock.lock()?;
+        let written = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .and_then(|mut file| {
+                let offset = file.metadata()?.len();
+                writeln!(file, "{}", line)?;
+                Ok(offset)
+            });
+        lock.unlock()?;
+
+        self.offsets.insert(hash, written?);
+
+        Ok(())
+    }
+
+    fn read(&self, hash: &[u8; 32]) -> anyhow::Result<Option<V>> {
+        let Some(offset) = self.offsets.get(hash).map(|offset| *offset) else {
+            return Ok(None);
+        };
+
+        let lock = self.lock()?;
+        lock.lock_shared()?;
+        let record = self.record(offset).and_then(|record| match record {
+            Some(record) if record.0 == *hash => Ok(Some(record.1)),
+            // Compacted by another instance since the offsets were loaded
+            _ => {
+                self.load()?;
+                match self.offsets.get(hash).map(|offset| *offset) {
+                    Some(offset) => Ok(self
+                        .record(offset)?
+                        .filter(|record| record.0 == *hash)
+                        .map(|record| record.1)),
+                    None => Ok(None),
+                }
+            }
+        });
+        lock.unlock()?;
+
+        record
+    }
+
+    /// The record starting at `offset`, `None` when there is no valid one.
+    fn record(&self, offset: u64) -> anyhow::Result<Option<([u8; 32], V)>> {
+        let mut file = File::open(&self.path)?;
+        file.seek(SeekFrom::Start(offset))?;
+
+        let mut line = String::new();
+        BufReader::new(file).read_line(&mut line)?;
+
+        Ok(self.parse(&line))
+    }
+
+    fn parse(&self, line: &str) -> Option<([u8; 32], V)> {
+        serde_json::from_str::<Record<V>>(line)
+            .ok()
+            .filter(|record| record.model == self.model)
+            .and_then(|record| {
+                blake3::Hash::from_hex(&record.hash)
+                    .ok()
+                    .map(|hash| (*hash.as_bytes(), record.value))
+            })
+    }
+
+    /// Reads the offsets of the records from the file, returns the number of lines which are
+    /// not needed: cut short by a crash, or duplicates written by another instance.
+    fn load(&self) -> anyhow::Result<usize> {
+        self.offsets.clear();
+        let mut stale = 0;
+
+        let file = match File::open(&self.path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(0),
+            Err(e) => return Err(e.into()),
+        };
+
+        let mut reader = BufReader::new(file);
+        let mut offset = 0;
+        let mut line = String::new();
+        loop {
+            line.clear();
+            let read = reader.read_line(&mut line)?;
+            if read == 0 {
+                break;
+            }
+
+            match self.parse(&line) {
+                Some((hash, _)) if !self.offsets.contains_key(&hash) => {
+                    self.offsets.insert(hash, offset);
+                }
+                _ => stale += 1,
+            }
+            offset += u64::try_from(read)?;
+        }
+
+        Ok(stale)
+    }
+
+    /// Rewrites the file with one record per hash, the caller holds the exclusive lock.
+    fn compact(&self) -> anyhow::Result<()> {
+        let temporary = self.path.with_extension("jsonl.tmp");
+
+        {
+            let mut writer = BufWriter::new(File::create(&temporary)?);
+            for entry in self.offsets.iter() {
+                if let Some((_, value)) = self.record(*entry.value())? {
+                    serde_json::to_writer(
+                        &mut writer,
+                        &Record {
+                            hash: blake3::Hash::from(*entry.key()).to_hex().to_string(),
+                            model: self.model.clone(),
+                            value,
+                        },
+                    )?;
+                    writeln!(writer)?;
+                }
+            }
+            writer.flush()?;
+        }
+
+        fs::rename(temporary, &self.path)?;
+        self.load()?;
+
+        Ok(())
+    }
+
+    /// The lock file, held by one thread of this instance at a time.
+    fn lock(&self) -> anyhow::Result<std::sync::MutexGuard<'_, File>> {
+        self.lock
+            .lock()
+            .map_err(|_| anyhow::anyhow!("Failed while locking the cache file"))
+    }
+
+    fn hit_rate(&self) -> HitRate {
+        let hits = self.hits.load(Ordering::Relaxed);
+        let lookups = self.lookups.load(Ordering::Relaxed);
+
+        #[allow(clippy::as_conversions)]
+        let rate = match lookups {
+            0 => 0.0,
+            lookups => hits as f64 / lookups as f64,
+        };
+
+        HitRate {
+            hits,
+            lookups,
+            rate,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::ensure;
+
+    use super::Table;
+
+    #[test]
+    fn test_table_reload() -> anyhow::Result<()> {
+        let directory = std::env::temp_dir().join(format!("marky-cache-{}", std::process::id()));
+        std::fs::create_dir_all(&directory)?;
+        let hash = *blake3::hash(b"block").as_bytes();
+
+        let table = Table::<String>::open(&directory, "summaries", "model-a")?;
+        table.put(hash, &"summary".to_string())?;
+        drop(table);
+
+        let table = Table::<String>::open(&directory, "summaries", "model-a")?;
+        ensure!(table.get(&hash).as_deref() == Some("summary"));
+        drop(table);
+
+        // Another model has a table of its own, switching back keeps the values of the first
+        let table = Table::<String>::open(&directory, "summaries", "model-b")?;
+        ensure!(table.get(&hash).is_none());
+        table.put(hash, &"other".to_string())?;
+        drop(table);
+
+        let table = Table::<String>::open(&directory, "summaries", "model-a")?;
+        ensure!(table.get(&hash).as_deref() == Some("summary"));
+
+        // A second instance appending the same value leaves a duplicate behind, the first
+        // instance finds its value again once the second compacts the file
+        let other = Table::<String>::open(&directory, "summaries", "model-a")?;
+        let second = *blake3::hash(b"second").as_bytes();
+        other.put(second, &"second".to_string())?;
+        table.put(second, &"second".to_string())?;
+        drop(other);
+
+        let other = Table::<String>::open(&directory, "summaries", "model-a")?;
+        ensure!(other.offsets.len() == 2);
+        ensure!(table.get(&hash).as_deref() == Some("summary"));
+        ensure!(table.get(&second).as_deref() == Some("second"));
+        drop((table, other));
+
+        std::fs::remove_dir_all(&directory)?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,92 @@
+use dashmap::DashMap;
+use faiss::Index;
+
+/// Flat inner-product faiss index over the normalized embeddings, i.e. scores are cosine
+/// similarities.
+///
+/// The index is rebuilt lazily, whenever the embedding registry changed since the last search.
+#[derive(Default)]
+pub(super) struct VectorIndex {
+    index: Option<faiss::IndexImpl>,
+    labels: Vec<[u8; 32]>,
+    stale: bool,
+}
+
+impl VectorIndex {
+    pub(super) fn invalidate(&mut self) {
+        self.stale = true;
+    }
+
+    pub(super) fn search(
+        &mut self,
+        embeddings: &DashMap<[u8; 32], Vec<f32>>,
+        query: &[f32],
+        k: usize,
+    ) -> anyhow::Result<Vec<([u8; 32], f32)>> {
+        if self.stale || self.index.is_none() {
+            self.rebuild(embeddings, query.len())?;
+        }
+
+        let Some(index) = self.index.as_mut() else {
+            return Ok(vec![]);
+        };
+
+        let k = k.min(self.labels.len());
+        if k == 0 || index.d() != u32::try_from(query.len())? {
+            return Ok(vec![]);
+        }
+
+        let result = index.search(&normalize(query), k)?;
+
+        Ok(result
+            .labels
+            .into_iter()
+            .zip(result.distances)
+            .filter_map(|(label, score)| {
+                let position = usize::try_from(label.get()?).ok()?;
+                self.labels.get(position).map(|hash| (*hash, score))
+            })
+            .collect())
+    }
+
+    fn rebuild(
+        &mut self,
+        embeddings: &DashMap<[u8; 32], Vec<f32>>,
+        dimension: usize,
+    ) -> anyhow::Result<()> {
+        let mut labels = Vec::with_capacity(embeddings.len());
+        let mut data = Vec::with_capacity(embeddings.len() * dimension);
+
+        for entry in embeddings.iter() {
+            if entry.value().len() != dimension {
+                continue;
+            }
+
+            labels.push(*entry.key());
+            data.extend(normalize(entry.value()));
+        }
+
+        let mut index = faiss::index_factory(
+            u32::try_from(dimension)?,
+            "Flat",
+            faiss::MetricType::InnerProduct,
+        )?;
+        index.add(&data)?;
+
+        self.index = Some(index);
+        self.labels = labels;
+        self.stale = false;
+
+        Ok(())
+    }
+}
+
+fn normalize(vector: &[f32]) -> Vec<f32> {
+    let norm = vector.iter().map(|value| value * value).sum::<f32>().sqrt();
+
+    if norm == 0.0 {
+        return vector.to_vec();
+    }
+
+    vector.iter().map(|value| value / norm).collect()
+}
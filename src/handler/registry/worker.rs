@@ -0,0 +1,297 @@
+use std::{
+    collections::{HashSet, VecDeque},
+    sync::{Arc, Condvar, Mutex, OnceLock},
+};
+
+use dashmap::DashMap;
+use rust_bert::pipelines::{
+    keywords_extraction::KeywordExtractionModel, summarization::SummarizationModel,
+};
+
+use super::{Load, Models, Queue as Depth, cache::Cache};
+
+/// Number of blocks handed to a model at once.
+const BATCH_SIZE: usize = 8;
+
+const KEYWORD_SCORE_THRESHOLD: f32 = 0.4;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub(super) enum Task {
+    Keywords,
+    Summary,
+}
+
+struct Job {
+    task: Task,
+    hash: [u8; 32],
+    text: String,
+}
+
+#[derive(Default)]
+struct State {
+    pending: VecDeque<Job>,
+    /// Jobs either pending or being processed, a block is never queued twice for a task.
+    queued: HashSet<(Task, [u8; 32])>,
+    models: Models,
+    /// Failures not reported yet, the worker thread has no way to reach the client itself.
+    errors: Vec<String>,
+}
+
+#[derive(Default)]
+struct Queue {
+    state: Mutex<State>,
+    ready: Condvar,
+}
+
+/// Where the worker stores its results.
+pub(super) struct Sink {
+    pub(super) keywords: Arc<DashMap<[u8; 32], Vec<String>>>,
+    pub(super) summaries: Arc<DashMap<[u8; 32], String>>,
+    pub(super) cache: Arc<OnceLock<Cache>>,
+}
+
+/// Dedicated thread owning the rust-bert models, they are loaded once on first use and kept for
+/// the lifetime of the server. Blocks are queued per task and processed in batches.
+pub(super) struct Worker {
+    queue: Arc<Queue>,
+}
+
+impl Worker {
+    pub(super) fn spawn(sink: Sink) -> anyhow::Result<Self> {
+        let queue = Arc::new(Queue::default());
+
+        let worker_queue = queue.clone();
+        std::thread::Builder::new()
+            .name("marky-models".to_string())
+            .spawn(move || run(&worker_queue, &sink))?;
+
+        Ok(Self { queue })
+    }
+
+    pub(super) fn submit(&self, task: Task, block: &super::super::Block) -> anyhow::Result<()> {
+        let mut state = self
+            .queue
+            .state
+            .lock()
+            .map_err(|_| anyhow::anyhow!("Failed while locking the model queue"))?;
+
+        if state.queued.insert((task, block.metadata.hash)) {
+            state.pending.push_back(Job {
+                task,
+                hash: block.metadata.hash,
+                text: block.text.clone(),
+            });
+            self.queue.ready.notify_one();
+        }
+
+        Ok(())
+    }
+
+    /// Drops the pending jobs of the blocks, jobs already handed to a model still complete.
+    pub(super) fn cancel(&self, hashes: &HashSet<[u8; 32]>) -> anyhow::Result<()> {
+        let mut state = self
+            .queue
+            .state
+            .lock()
+            .map_err(|_| anyhow::anyhow!("Failed while locking the model queue"))?;
+
+        let State {
+            pending, queued, ..
+        } = &mut *state;
+        pending.retain(|job| {
+            let keep = !hashes.contains(&job.hash);
+            if !keep {
+                queued.remove(&(job.task, job.hash));
+            }
+            keep
+        });
+
+        Ok(())
+    }
+
+    /// Moves the pending jobs of a block to the front of the queue.
+    pub(super) fn prioritize(&self, hash: &[u8; 32]) -> anyhow::Result<()> {
+        let mut state = self
+            .queue
+            .state
+            .lock()
+            .map_err(|_| anyhow::anyhow!("Failed while locking the model queue"))?;
+
+        let (urgent, rest): (VecDeque<_>, VecDeque<_>) =
+            state.pending.drain(..).partition(|job| &job.hash == hash);
+        state.pending = urgent;
+        state.pending.extend(rest);
+
+        Ok(())
+    }
+
+    /// Whether a block is queued, or being processed, for the task.
+    pub(super) fn pending(&self, task: Task, hash: &[u8; 32]) -> bool {
+        self.queue
+            .state
+            .lock()
+            .is_ok_and(|state| state.queued.contains(&(task, *hash)))
+    }
+
+    /// Takes the failures since the last call.
+    pub(super) fn take_errors(&self) -> Vec<String> {
+        self.queue
+            .state
+            .lock()
+            .map(|mut state| std::mem::take(&mut state.errors))
+            .unwrap_or_default()
+    }
+
+    /// Queue depth per task and the load state of the models, embeddings never go through here.
+    pub(super) fn status(&self) -> anyhow::Result<(Depth, Models)> {
+        let state = self
+            .queue
+            .state
+            .lock()
+            .map_err(|_| anyhow::anyhow!("Failed while locking the model queue"))?;
+
+        let depth = |task| state.queued.iter().filter(|(t, _)| *t == task).count();
+
+        Ok((
+            Depth {
+                keywords: depth(Task::Keywords),
+                summaries: depth(Task::Summary),
+                embeddings: 0,
+            },
+            state.models,
+        ))
+    }
+}
+
+#[derive(Default)]
+struct Loaded {
+    keywords: Option<KeywordExtractionModel<'static>>,
+    summarization: Option<SummarizationModel>,
+}
+
+fn run(queue: &Queue, sink: &Sink) {
+    let mut models = Loaded::default();
+
+    while let Some((task, batch)) = next_batch(queue) {
+        // A failing batch is dropped, its blocks are queued again on their next analysis
+        let result = process(queue, &mut models, sink, task, &batch);
+
+        if let Ok(mut state) = queue.state.lock() {
+            for job in &batch {
+                state.queued.remove(&(task, job.hash));
+            }
+            if let Err(e) = result {
+                state
+                    .errors
+                    .push(format!("Failed to process a batch: {}", e));
+            }
+        }
+    }
+}
+
+/// Waits for work, then takes the jobs at the front of the queue sharing the task of the first
+/// one, up to `BATCH_SIZE`.
+fn next_batch(queue: &Queue) -> Option<(Task, Vec<Job>)> {
+    let mut state = queue.state.lock().ok()?;
+    while state.pending.is_empty() {
+        state = queue.ready.wait(state).ok()?;
+    }
+
+    let task = state.pending.front()?.task;
+
+    let mut batch = Vec::with_capacity(BATCH_SIZE);
+    while batch.len() < BATCH_SIZE && state.pending.front().is_some_and(|job| job.task == task) {
+        batch.extend(state.pending.pop_front());
+    }
+
+    Some((task, batch))
+}
+
+fn process(
+    queue: &Queue,
+    models: &mut Loaded,
+    sink: &Sink,
+    task: Task,
+    batch: &[Job],
+) -> anyhow::Result<()> {
+    let texts = batch
+        .iter()
+        .map(|job| job.text.as_str())
+        .collect::<Vec<_>>();
+
+    match task {
+        Task::Keywords => {
+            let model = match &mut models.keywords {
+                Some(model) => model,
+                None => models.keywords.insert(load(queue, task, || {
+                    KeywordExtractionModel::new(Default::default())
+                })?),
+            };
+
+            for (job, keywords) in batch.iter().zip(model.predict(&texts)?) {
+                let keywords = keywords
+                    .into_iter()
+                    .filter(|keyword| keyword.score > KEYWORD_SCORE_THRESHOLD)
+                    .map(|keyword| keyword.text)
+                    .collect::<Vec<_>>();
+
+                if let Some(cache) = sink.cache.get()
+                    && let Err(e) = cache.keywords.put(job.hash, &keywords)
+                {
+                    report(queue, format!("Failed to cache keywords: {}", e));
+                }
+                sink.keywords.insert(job.hash, keywords);
+            }
+        }
+        Task::Summary => {
+            let model = match &mut models.summarization {
+                Some(model) => model,
+                None => models.summarization.insert(load(queue, task, || {
+                    SummarizationModel::new(Default::default())
+                })?),
+            };
+
+            for (job, summary) in batch.iter().zip(model.summarize(&texts)?) {
+                if let Some(cache) = sink.cache.get()
+                    && let Err(e) = cache.summaries.put(job.hash, &summary)
+                {
+                    report(queue, format!("Failed to cache a summary: {}", e));
+                }
+                sink.summaries.insert(job.hash, summary);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Keeps a failure for the next `take_errors`, the in-memory results do not depend on the cache.
+fn report(queue: &Queue, error: String) {
+    if let Ok(mut state) = queue.state.lock() {
+        state.errors.push(error);
+    }
+}
+
+/// Loads the model of a task, keeping its load state up to date for `marky/status`.
+fn load<M, E>(queue: &Queue, task: Task, model: impl FnOnce() -> Result<M, E>) -> anyhow::Result<M>
+where
+    E: std::error::Error + Send + Sync + 'static,
+{
+    let set = |load| {
+        if let Ok(mut state) = queue.state.lock() {
+            match task {
+                Task::Keywords => state.models.keywords = load,
+                Task::Summary => state.models.summarization = load,
+            }
+        }
+    };
+
+    set(Load::Loading);
+    let model = model();
+    set(match model {
+        Ok(_) => Load::Loaded,
+        Err(_) => Load::Failed,
+    });
+
+    Ok(model?)
+}
@@ -0,0 +1,145 @@
+use std::time::Duration;
+
+use unicode_segmentation::UnicodeSegmentation;
+
+use super::{BlockKind, Stat};
+
+/// Average silent reading speed of adults, in words per minute.
+const READING_SPEED: f32 = 238.0;
+
+/// Prose is measured in sentences, code and markup in lines.
+pub(super) fn measure(kind: &BlockKind, text: &str) -> Stat {
+    let lines = match kind.is_prose() {
+        true => text
+            .unicode_sentences()
+            .filter(|sentence| sentence.unicode_words().next().is_some())
+            .count(),
+        false => text.lines().count(),
+    };
+
+    let words = text.unicode_words().collect::<Vec<_>>();
+    let word_characters = words
+        .iter()
+        .map(|word| word.graphemes(true).count())
+        .sum::<usize>();
+
+    let characters = text
+        .graphemes(true)
+        .filter(|grapheme| !grapheme.chars().all(char::is_whitespace))
+        .count();
+
+    #[allow(clippy::as_conversions)]
+    let avg_word_size = word_characters as f32 / words.len().max(1) as f32;
+
+    derive(
+        lines,
+        words.len(),
+        characters,
+        avg_word_size,
+        words.iter().map(|word| syllables(word)).sum(),
+    )
+}
+
+impl Stat {
+    /// Combined statistics of several blocks, e.g. a selection or a section.
+    pub fn total<'a>(stats: impl IntoIterator<Item = &'a Self>) -> Self {
+        let (mut lines, mut words, mut characters, mut syllables) = (0, 0, 0, 0);
+        let mut word_characters = 0.0;
+
+        for stat in stats {
+            lines += stat.lines;
+            words += stat.words;
+            characters += stat.characters;
+            syllables += stat.syllables;
+
+            #[allow(clippy::as_conversions)]
+            let block_word_characters = stat.avg_word_size * stat.words as f32;
+            word_characters += block_word_characters;
+        }
+
+        #[allow(clippy::as_conversions)]
+        let avg_word_size = word_characters / words.max(1) as f32;
+
+        derive(lines, words, characters, avg_word_size, syllables)
+    }
+}
+
+#[allow(clippy::as_conversions)]
+fn derive(
+    lines: usize,
+    words: usize,
+    characters: usize,
+    avg_word_size: f32,
+    syllables: usize,
+) -> Stat {
+    let words_per_line = words as f32 / lines.max(1) as f32;
+    let syllables_per_word = syllables as f32 / words.max(1) as f32;
+
+    let (reading_ease, grade_level) = match words {
+        0 => (0.0, 0.0),
+        _ => (
+            206.835 - 1.015 * words_per_line - 84.6 * syllables_per_word,
+            0.39 * words_per_line + 11.8 * syllables_per_word - 15.59,
+        ),
+    };
+
+    Stat {
+        lines,
+        words,
+        avg_line_size: words_per_line,
+        characters,
+        avg_word_size,
+        syllables,
+        reading_ease,
+        grade_level,
+        reading_time: Duration::from_secs_f32(words as f32 / READING_SPEED * 60.0),
+    }
+}
+
+/// Estimates the syllables of an English word from its groups of vowels, a silent final `e` does
+/// not count. Words without any vowel, e.g. numbers or other scripts, count as one.
+fn syllables(word: &str) -> usize {
+    let word = word.to_lowercase();
+    let is_vowel = |c: char| matches!(c, 'a' | 'e' | 'i' | 'o' | 'u' | 'y');
+
+    let mut count = 0;
+    let mut previous = false;
+    for c in word.chars() {
+        let vowel = is_vowel(c);
+        if vowel && !previous {
+            count += 1;
+        }
+        previous = vowel;
+    }
+
+    if count > 1 && word.ends_with('e') && !word.ends_with("le") && !word.ends_with("ee") {
+        count -= 1;
+    }
+
+    count.max(1)
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::ensure;
+
+    use super::super::BlockKind;
+
+    #[test]
+    fn test_measure_prose() -> anyhow::Result<()> {
+        let stat = super::measure(
+            &BlockKind::Paragraph,
+            "Use a tool, e.g. marky, on v1.2.3 docs. Does it help? It does!",
+        );
+
+        ensure!(stat.lines == 3);
+        ensure!(stat.words == 13);
+        ensure!(stat.characters == 50);
+
+        ensure!(super::syllables("readable") == 3);
+        ensure!(super::syllables("the") == 1);
+        ensure!(super::syllables("2024") == 1);
+
+        Ok(())
+    }
+}
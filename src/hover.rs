@@ -1,13 +1,180 @@
 use tower_lsp::lsp_types;
 
+use crate::handler;
+
 mod point;
 mod range;
+mod section;
+
+/// Command summarizing a selection with the LLM, takes the document URI and the range.
+pub const SUMMARIZE_SELECTION: &str = "marky.summarizeSelection";
+
+/// Parameters of the custom `marky/hoverRange` request, a hover over a selection.
+#[derive(Debug, Clone, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RangeHoverParams {
+    pub text_document: lsp_types::TextDocumentIdentifier,
+    pub range: lsp_types::Range,
+}
 
 pub async fn hover(
     ctx: &crate::Backend,
     params: lsp_types::HoverParams,
 ) -> anyhow::Result<Option<lsp_types::Hover>> {
+    let uri = params.text_document_position_params.text_document.uri;
     let loc = params.text_document_position_params.position;
 
-    point::hover(ctx, loc).await
+    // Hovering inside the selection describes the whole selection. Code actions are also asked
+    // for on cursor moves, so the selection is only kept for the next hover.
+    let selection = ctx.selections.remove(&uri).map(|(_, selection)| selection);
+    if let Some(lsp_types::Range { start, end }) = selection
+        && start <= loc
+        && loc <= end
+    {
+        return range::hover(ctx, &uri, start, end).await;
+    }
+
+    point::hover(ctx, &uri, loc).await
+}
+
+/// Remembers the selection of a code action request, an empty one clears it.
+pub fn select(ctx: &crate::Backend, uri: &lsp_types::Url, range: lsp_types::Range) {
+    if range.start == range.end {
+        ctx.selections.remove(uri);
+    } else {
+        ctx.selections.insert(uri.clone(), range);
+    }
+}
+
+/// The command offered for a non-empty selection, see `SUMMARIZE_SELECTION`.
+pub fn summarize_command(uri: &lsp_types::Url, range: lsp_types::Range) -> lsp_types::Command {
+    lsp_types::Command {
+        title: "Summarize selection".to_string(),
+        command: SUMMARIZE_SELECTION.to_string(),
+        arguments: Some(vec![serde_json::json!(uri), serde_json::json!(range)]),
+    }
+}
+
+pub async fn execute_command(
+    ctx: &crate::Backend,
+    params: lsp_types::ExecuteCommandParams,
+) -> anyhow::Result<Option<serde_json::Value>> {
+    if params.command != SUMMARIZE_SELECTION {
+        anyhow::bail!("Unknown command: {}", params.command);
+    }
+
+    let (uri, range): (lsp_types::Url, lsp_types::Range) =
+        serde_json::from_value(serde_json::Value::Array(params.arguments))?;
+
+    let summary = range::summarize(ctx, &uri, range).await?;
+    ctx.client
+        .show_message(lsp_types::MessageType::INFO, &summary)
+        .await;
+
+    Ok(Some(serde_json::Value::String(summary)))
+}
+
+pub async fn hover_range(
+    ctx: &crate::Backend,
+    params: RangeHoverParams,
+) -> anyhow::Result<Option<lsp_types::Hover>> {
+    let uri = params.text_document.uri;
+    let lsp_types::Range { start, end } = params.range;
+
+    if start == end {
+        return point::hover(ctx, &uri, start).await;
+    }
+
+    range::hover(ctx, &uri, start, end).await
+}
+
+/// Statistics as TOML entries, readability only means something for prose.
+fn statistics(stat: &handler::Stat, prose: bool) -> Vec<String> {
+    let mut entries = vec![
+        format!("lines = {}", stat.lines),
+        format!("words = {}", stat.words),
+        format!("characters = {}", stat.characters),
+        format!("syllables = {}", stat.syllables),
+        format!("average.line_size = {}", stat.avg_line_size),
+        format!("average.word_size = {:.1}", stat.avg_word_size),
+    ];
+
+    if prose {
+        entries.push(format!(
+            "readability.flesch_reading_ease = {:.1}",
+            stat.reading_ease
+        ));
+        entries.push(format!(
+            "readability.flesch_kincaid_grade = {:.1}",
+            stat.grade_level
+        ));
+    }
+
+    let seconds = stat.reading_time.as_secs().max(1);
+    entries.push(match seconds {
+        0..60 => format!("reading_time = \"{}s\"", seconds),
+        _ => format!("reading_time = \"{}m {}s\"", seconds / 60, seconds % 60),
+    });
+
+    entries
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::ensure;
+    use tower_lsp::lsp_types;
+
+    #[tokio::test]
+    async fn test_selection_hover() -> anyhow::Result<()> {
+        let (service, _) = tower_lsp::LspService::new(crate::Backend::new);
+        let ctx = service.inner();
+
+        let mut parser = tree_sitter::Parser::new();
+        parser.set_language(tree_sitter_md::language())?;
+
+        let uri = lsp_types::Url::parse("file:///notes/a.md")?;
+        let handle = crate::handler::Handle::new("First.\n\nSecond.\n", &mut parser)?;
+        ctx.project.documents.insert(uri.clone(), handle).await;
+
+        let position = |line, character| lsp_types::Position { line, character };
+        let hover = |line, character| {
+            super::hover(
+                ctx,
+                lsp_types::HoverParams {
+                    text_document_position_params: lsp_types::TextDocumentPositionParams {
+                        text_document: lsp_types::TextDocumentIdentifier { uri: uri.clone() },
+                        position: position(line, character),
+                    },
+                    work_done_progress_params: Default::default(),
+                },
+            )
+        };
+        let selection = lsp_types::Range {
+            start: position(0, 0),
+            end: position(2, 7),
+        };
+
+        super::select(ctx, &uri, selection);
+        let first = hover(0, 2).await?;
+        ensure!(first.and_then(|hover| hover.range) == Some(selection));
+
+        // The next hover describes the block under the cursor again
+        let second = hover(0, 2).await?;
+        ensure!(second.and_then(|hover| hover.range) != Some(selection));
+
+        // A line-wise selection ends on the next line, the block there is not part of it
+        let blocks = |hover: Option<lsp_types::Hover>| match hover.map(|hover| hover.contents) {
+            Some(lsp_types::HoverContents::Markup(markup)) => markup
+                .value
+                .lines()
+                .find_map(|line| line.strip_prefix("blocks = ").map(String::from)),
+            _ => None,
+        };
+        let lines = super::range::hover(ctx, &uri, position(0, 0), position(2, 0)).await?;
+        ensure!(blocks(lines).as_deref() == Some("1"));
+        let both = super::range::hover(ctx, &uri, position(0, 0), position(2, 1)).await?;
+        ensure!(blocks(both).as_deref() == Some("2"));
+
+        Ok(())
+    }
 }
@@ -1,19 +1,29 @@
 use tower_lsp::lsp_types;
 
+use crate::handler::{self, BlockKind};
+
+#[cfg(feature = "intelligence")]
+const RELATED_LIMIT: usize = 5;
+
 pub async fn hover(
     ctx: &crate::Backend,
+    uri: &lsp_types::Url,
     loc: lsp_types::Position,
 ) -> anyhow::Result<Option<lsp_types::Hover>> {
-    if let Some(handle) = ctx.project.current_file.read().await.as_ref() {
-        #[allow(clippy::as_conversions)]
-        let block = handle.get_block(loc.line as usize, loc.character as usize);
+    if let Some(handle) = ctx.project.documents.get(uri).await {
+        let (row, col) = handle.point(loc);
+        let block = handle.get_block(row, col);
+
+        if let Some(hover) = super::section::hover(&handle, row, block.as_ref())? {
+            return Ok(Some(hover));
+        }
+
         if let Some(block) = block {
-            let stats = [
-                format!("lines = {}", block.stat.lines),
-                format!("words = {}", block.stat.words),
-                format!("average.line_size = {}", block.stat.avg_line_size),
-            ]
-            .join("\n");
+            let stats = describe(&block.kind)
+                .into_iter()
+                .chain(super::statistics(&block.stat, block.kind.is_prose()))
+                .collect::<Vec<_>>()
+                .join("\n");
 
             #[cfg(feature = "intelligence")]
             let keywords = ctx.project.registry.get_keywords(&block);
@@ -25,49 +35,63 @@ pub async fn hover(
             #[cfg(not(feature = "intelligence"))]
             let summary: Option<String> = None;
 
+            // Blocks queued for the models get a note rather than nothing
+            #[cfg(feature = "intelligence")]
+            let pending = (
+                ctx.project.registry.keywords_pending(&block),
+                ctx.project.registry.summary_pending(&block),
+            );
+            #[cfg(not(feature = "intelligence"))]
+            let pending = (false, false);
+
             let mut data = ["[statistics]", &stats].join("\n");
 
-            match (keywords, summary) {
-                (None, None) => {}
-                (a, b) => {
-                    let mut list = vec!["".to_string(), "[analytics]".to_string()];
-
-                    if let Some(keyword) = a {
-                        let keywords = keyword
-                            .iter()
-                            .map(|value| format!("\"{}\"", value))
-                            .collect::<Vec<_>>()
-                            .join(", ");
-                        list.push(format!("keywords = [{}]", keywords));
-                    }
-
-                    if let Some(summary) = b {
-                        list.push(format!("summary = \"{}\"", summary));
-                    }
-                    data.push_str(&list.join("\n"));
+            if block.kind == BlockKind::Frontmatter {
+                data.push_str(&frontmatter(&handle)?);
+            }
+
+            let mut list = vec!["".to_string(), "[analytics]".to_string()];
+
+            match keywords {
+                Some(keyword) => {
+                    let keywords = keyword
+                        .iter()
+                        .map(|value| format!("\"{}\"", value))
+                        .collect::<Vec<_>>()
+                        .join(", ");
+                    list.push(format!("keywords = [{}]", keywords));
                 }
+                None if pending.0 => list.push("# keywords pending…".to_string()),
+                None => {}
             }
 
-            #[allow(clippy::as_conversions)]
-            let start = lsp_types::Position {
-                line: block.start.0 as u32,
-                character: block.start.1 as u32,
-            };
+            match summary {
+                Some(summary) => list.push(format!("summary = \"{}\"", summary)),
+                None if pending.1 => list.push("# summary pending…".to_string()),
+                None => {}
+            }
 
-            #[allow(clippy::as_conversions)]
-            let end = lsp_types::Position {
-                line: block.end.0 as u32,
-                character: block.end.1 as u32,
-            };
+            if list.len() > 2 {
+                data.push_str(&list.join("\n"));
+            }
+
+            #[cfg(feature = "intelligence")]
+            let related = related(ctx, uri, &block).await;
+            #[cfg(not(feature = "intelligence"))]
+            let related: Vec<String> = vec![];
+
+            let mut value = format!("```toml\n{}\n```", data);
+            if !related.is_empty() {
+                value.push_str("\n\n[related]\n");
+                value.push_str(&related.join("\n"));
+            }
 
             let hover = lsp_types::Hover {
-                contents: lsp_types::HoverContents::Scalar(
-                    lsp_types::MarkedString::LanguageString(lsp_types::LanguageString {
-                        language: "toml".to_string(),
-                        value: data,
-                    }),
-                ),
-                range: Some(lsp_types::Range { start, end }),
+                contents: lsp_types::HoverContents::Markup(lsp_types::MarkupContent {
+                    kind: lsp_types::MarkupKind::Markdown,
+                    value,
+                }),
+                range: Some(handle.range(block.start, block.end)),
             };
 
             return Ok(Some(hover));
@@ -76,3 +100,118 @@ pub async fn hover(
 
     Ok(None)
 }
+
+/// The parsed frontmatter as a TOML table, values TOML can not express (e.g. `null`) are left out.
+fn frontmatter(handle: &handler::Handle) -> anyhow::Result<String> {
+    let Some(frontmatter) = handle
+        .frontmatter
+        .read()
+        .map_err(|_| anyhow::anyhow!("Failed while reading the frontmatter"))?
+        .clone()
+    else {
+        return Ok(String::new());
+    };
+
+    let mut list = vec!["".to_string(), "[frontmatter]".to_string()];
+
+    if let Some(error) = &frontmatter.error {
+        let message = error.message.lines().next().unwrap_or_default();
+        list.push(format!("# invalid: {}", message));
+    }
+
+    for key in &frontmatter.keys {
+        let Some(value) = frontmatter.data.get(&key.name) else {
+            continue;
+        };
+        let Ok(value) = toml::Value::try_from(value) else {
+            continue;
+        };
+
+        let name = match key
+            .name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+        {
+            true => key.name.clone(),
+            false => serde_json::to_string(&key.name)?,
+        };
+        list.push(format!("{} = {}", name, value));
+    }
+
+    Ok(list.join("\n"))
+}
+
+/// The kind of the block and what is specific to it, as TOML entries.
+fn describe(kind: &BlockKind) -> Vec<String> {
+    let mut entries = vec![format!("kind = \"{}\"", kind.name())];
+
+    match kind {
+        BlockKind::Heading { level } => entries.push(format!("level = {}", level)),
+        BlockKind::ListItem { depth, ordered } => {
+            entries.push(format!("depth = {}", depth));
+            entries.push(format!("ordered = {}", ordered));
+        }
+        BlockKind::TableCell { row, column } => {
+            entries.push(format!("row = {}", row));
+            entries.push(format!("column = {}", column));
+        }
+        BlockKind::Code {
+            language: Some(language),
+        } => entries.push(format!("language = \"{}\"", language)),
+        BlockKind::BlockQuote { depth } => entries.push(format!("depth = {}", depth)),
+        _ => {}
+    }
+
+    entries
+}
+
+/// Lists the blocks closest to `block` in the embedding space, as `file:line` links annotated with
+/// the heading they live under.
+#[cfg(feature = "intelligence")]
+async fn related(
+    ctx: &crate::Backend,
+    uri: &lsp_types::Url,
+    block: &handler::Block,
+) -> Vec<String> {
+    let Some(embedding) = ctx.project.registry.get_embedding(block) else {
+        return vec![];
+    };
+
+    let Ok(hits) = ctx
+        .project
+        .registry
+        .nearest(&embedding, RELATED_LIMIT + 1)
+        .await
+    else {
+        return vec![];
+    };
+
+    let mut related = Vec::new();
+    for (hash, score) in hits {
+        for (target, handle, other) in ctx.project.locate(&hash).await {
+            if &target == uri && other.start == block.start {
+                continue;
+            }
+
+            let heading = handle.heading_at(other.start.0);
+            let title = heading.map_or_else(
+                || other.text.chars().take(40).collect::<String>(),
+                |heading| heading.text,
+            );
+            let file = target
+                .path_segments()
+                .and_then(|mut segments| segments.next_back())
+                .unwrap_or_default()
+                .to_string();
+            let line = other.start.0 + 1;
+
+            related.push(format!(
+                "- [{}:{}]({}#L{}) {} ({:.2})",
+                file, line, target, line, title, score
+            ));
+        }
+    }
+    related.truncate(RELATED_LIMIT);
+
+    related
+}
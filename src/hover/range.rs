@@ -1,11 +1,134 @@
-use anyhow::bail;
 use tower_lsp::lsp_types;
 
-#[allow(dead_code)]
+use crate::handler;
+use crate::llm::Llm;
+
+const SUMMARY_INSTRUCTION: &str =
+    "Summarize the following Markdown selection in two or three sentences.";
+
 pub async fn hover(
-    _ctx: &crate::Backend,
-    _start: lsp_types::Position,
-    _end: lsp_types::Position,
+    ctx: &crate::Backend,
+    uri: &lsp_types::Url,
+    start: lsp_types::Position,
+    end: lsp_types::Position,
 ) -> anyhow::Result<Option<lsp_types::Hover>> {
-    bail!("range hover not implemented");
+    let Some(handle) = ctx.project.handle(uri).await else {
+        return Ok(None);
+    };
+
+    let (from, to) = (handle.point(start), handle.point(end));
+
+    let blocks = handle
+        .blocks
+        .read()
+        .map_err(|_| anyhow::anyhow!("Failed while reading the blocks"))?
+        .iter()
+        // The end of the range is exclusive, line-wise selections end at the start of the next line
+        .filter(|block| block.end > from && block.start < to)
+        .cloned()
+        .collect::<Vec<_>>();
+
+    if blocks.is_empty() {
+        return Ok(None);
+    }
+
+    let stat = handler::Stat::total(blocks.iter().map(|block| &block.stat));
+    let prose = blocks.iter().any(|block| block.kind.is_prose());
+
+    let stats = [format!("blocks = {}", blocks.len())]
+        .into_iter()
+        .chain(super::statistics(&stat, prose))
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    let mut data = ["[statistics]", &stats].join("\n");
+
+    #[cfg(feature = "intelligence")]
+    let keywords = {
+        let mut keywords: Vec<String> = Vec::new();
+        for block in &blocks {
+            for keyword in ctx.project.registry.get_keywords(block).unwrap_or_default() {
+                if !keywords.contains(&keyword) {
+                    keywords.push(keyword);
+                }
+            }
+        }
+        keywords
+    };
+    #[cfg(not(feature = "intelligence"))]
+    let keywords: Vec<String> = vec![];
+
+    // Summaries take an LLM round-trip, they are only computed through the command
+    let summary = handle
+        .text_in(lsp_types::Range { start, end })
+        .filter(|selection| !selection.trim().is_empty())
+        .map(|selection| {
+            ctx.summaries
+                .get(blake3::hash(selection.as_bytes()).as_bytes())
+                .map(|summary| summary.clone())
+        });
+
+    if !keywords.is_empty() || summary.is_some() {
+        let mut list = vec!["".to_string(), "[analytics]".to_string()];
+
+        if !keywords.is_empty() {
+            let keywords = keywords
+                .iter()
+                .map(|value| format!("\"{}\"", value))
+                .collect::<Vec<_>>()
+                .join(", ");
+            list.push(format!("keywords = [{}]", keywords));
+        }
+
+        match summary {
+            Some(Some(summary)) => list.push(format!("summary = \"{}\"", summary.trim())),
+            Some(None) => {
+                list.push("# summary: run the \"Summarize selection\" action".to_string())
+            }
+            None => {}
+        }
+        data.push_str(&list.join("\n"));
+    }
+
+    Ok(Some(lsp_types::Hover {
+        contents: lsp_types::HoverContents::Markup(lsp_types::MarkupContent {
+            kind: lsp_types::MarkupKind::Markdown,
+            value: format!("```toml\n{}\n```", data),
+        }),
+        range: Some(lsp_types::Range { start, end }),
+    }))
+}
+
+/// Summarizes the selected text, summaries are kept by the hash of the text so the range hover
+/// can show them.
+pub async fn summarize(
+    ctx: &crate::Backend,
+    uri: &lsp_types::Url,
+    range: lsp_types::Range,
+) -> anyhow::Result<String> {
+    let selection = ctx
+        .project
+        .handle(uri)
+        .await
+        .and_then(|handle| handle.text_in(range))
+        .filter(|selection| !selection.trim().is_empty())
+        .ok_or_else(|| anyhow::anyhow!("Nothing selected in {}", uri))?;
+
+    let hash = *blake3::hash(selection.as_bytes()).as_bytes();
+    if let Some(summary) = ctx.summaries.get(&hash) {
+        return Ok(summary.clone());
+    }
+
+    let summary = ctx
+        .artifacts
+        .read()
+        .await
+        .llm()?
+        .generate(SUMMARY_INSTRUCTION, &selection)
+        .await?
+        .trim()
+        .to_string();
+    ctx.summaries.insert(hash, summary.clone());
+
+    Ok(summary)
 }
@@ -0,0 +1,177 @@
+use tower_lsp::lsp_types;
+
+use crate::handler::{self, BlockKind, link};
+
+/// Totals of the section a heading opens on the heading, and of the whole document on its title
+/// line, the first one after the frontmatter, next to those of the heading found there. `None`
+/// anywhere else, the block under the cursor is described instead.
+pub fn hover(
+    handle: &handler::Handle,
+    row: usize,
+    block: Option<&handler::Block>,
+) -> anyhow::Result<Option<lsp_types::Hover>> {
+    let headings = handle
+        .headings
+        .read()
+        .map_err(|_| anyhow::anyhow!("Failed while reading the headings"))?
+        .clone();
+    let root = handler::heading::sections(&headings, handle.end());
+
+    let title = handle
+        .blocks
+        .read()
+        .map_err(|_| anyhow::anyhow!("Failed while reading the blocks"))?
+        .iter()
+        .find(|block| block.kind != BlockKind::Frontmatter)
+        .map(|block| block.start.0);
+    let on_title = title == Some(row);
+
+    let heading = match block {
+        Some(block) if matches!(block.kind, BlockKind::Heading { .. }) => root.find(block.start.0),
+        _ => root.find(row),
+    };
+
+    let sections = heading
+        .into_iter()
+        .chain(on_title.then_some(&root))
+        .collect::<Vec<_>>();
+    let Some(first) = sections.first() else {
+        return Ok(None);
+    };
+
+    let mut entries = Vec::new();
+    for section in &sections {
+        if !entries.is_empty() {
+            entries.push(String::new());
+        }
+        entries.extend(describe(handle, section)?);
+    }
+
+    let hover = lsp_types::Hover {
+        contents: lsp_types::HoverContents::Markup(lsp_types::MarkupContent {
+            kind: lsp_types::MarkupKind::Markdown,
+            value: format!("```toml\n{}\n```", entries.join("\n")),
+        }),
+        range: Some(handle.range(first.start, first.end)),
+    };
+
+    Ok(Some(hover))
+}
+
+/// The counts and statistics of a section, or of the whole document for the root.
+fn describe(
+    handle: &handler::Handle,
+    section: &handler::heading::Section,
+) -> anyhow::Result<Vec<String>> {
+    let within = |start: (usize, usize)| section.start <= start && start < section.end;
+
+    // Frontmatter is metadata, it is not read as part of the document
+    let blocks = handle
+        .blocks
+        .read()
+        .map_err(|_| anyhow::anyhow!("Failed while reading the blocks"))?
+        .iter()
+        .filter(|block| within(block.start) && block.kind != BlockKind::Frontmatter)
+        .cloned()
+        .collect::<Vec<_>>();
+
+    let links = handle
+        .links
+        .read()
+        .map_err(|_| anyhow::anyhow!("Failed while reading the links"))?
+        .iter()
+        .filter(|link| {
+            within(link.start) && matches!(link.kind, link::Kind::Inline | link::Kind::Reference)
+        })
+        .count();
+
+    let code_blocks = blocks
+        .iter()
+        .filter(|block| matches!(block.kind, BlockKind::Code { .. }))
+        .count();
+
+    let mut entries = match &section.heading {
+        Some(heading) => vec![
+            "[section]".to_string(),
+            format!("heading = {}", serde_json::to_string(&heading.text)?),
+            format!("level = {}", heading.level),
+            format!("subsections = {}", section.descendants()),
+        ],
+        None => vec![
+            "[document]".to_string(),
+            format!("sections = {}", section.descendants()),
+        ],
+    };
+    entries.push(format!("code_blocks = {}", code_blocks));
+    entries.push(format!("links = {}", links));
+    entries.push(format!("blocks = {}", blocks.len()));
+
+    let stat = handler::Stat::total(
+        blocks
+            .iter()
+            .filter(|block| block.kind.is_prose())
+            .map(|block| &block.stat),
+    );
+    let code = handler::Stat::total(
+        blocks
+            .iter()
+            .filter(|block| !block.kind.is_prose())
+            .map(|block| &block.stat),
+    );
+
+    entries.push(String::new());
+    entries.push("[statistics]".to_string());
+    entries.extend(super::statistics(&stat, true));
+    entries.push(format!("code_lines = {}", code.lines));
+
+    Ok(entries)
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::ensure;
+    use tower_lsp::lsp_types;
+
+    /// The tables shown when hovering the start of `row`.
+    fn tables(text: &str, row: usize) -> anyhow::Result<Vec<String>> {
+        let mut parser = tree_sitter::Parser::new();
+        parser.set_language(tree_sitter_md::language())?;
+        let handle = crate::handler::Handle::new(text, &mut parser)?;
+
+        let block = handle.get_block(row, 0);
+        let hover = super::hover(&handle, row, block.as_ref())?;
+
+        Ok(match hover.map(|hover| hover.contents) {
+            Some(lsp_types::HoverContents::Markup(markup)) => markup
+                .value
+                .lines()
+                .filter(|line| line.starts_with('[') && *line != "[statistics]")
+                .map(String::from)
+                .collect(),
+            _ => vec![],
+        })
+    }
+
+    #[test]
+    fn test_title_line() -> anyhow::Result<()> {
+        let both = ["[section]", "[document]"];
+
+        let heading = tables("# Title\n\nSome text.\n\n## Part\n\nMore text.\n", 0)?;
+        ensure!(heading == both, "{:?}", heading);
+
+        // The frontmatter keeps its own hover, the title line is the one after it
+        let frontmatter = "---\ntitle: Notes\n---\n\n# Title\n\nSome text.\n";
+        let delimiter = tables(frontmatter, 0)?;
+        ensure!(delimiter.is_empty(), "{:?}", delimiter);
+        let title = tables(frontmatter, 4)?;
+        ensure!(title == both, "{:?}", title);
+
+        let prose = tables("Some text.\n\n## Part\n\nMore text.\n", 0)?;
+        ensure!(prose == ["[document]"], "{:?}", prose);
+
+        let part = tables("Some text.\n\n## Part\n\nMore text.\n", 2)?;
+        ensure!(part == ["[section]"], "{:?}", part);
+
+        Ok(())
+    }
+}
@@ -1,21 +1,38 @@
-use std::sync::Arc;
+use std::collections::HashSet;
+use std::sync::{Arc, OnceLock};
 
+use dashmap::DashMap;
 use tokio::sync::RwLock;
-
-#[cfg(feature = "intelligence")]
 use tokio::task;
 
 use tower_lsp::jsonrpc::Result;
 use tower_lsp::{LanguageServer, lsp_types};
 
+#[macro_use]
+mod logging;
+
+mod action;
+#[cfg(feature = "intelligence")]
+mod analysis;
+mod completion;
 mod config;
+mod definition;
+mod diagnostic;
 pub mod embedding;
+mod folding;
 pub mod handler;
 mod hover;
+mod lint;
 mod llm;
-
-#[macro_use]
-mod logging;
+mod progress;
+mod reference;
+mod rename;
+mod schema;
+mod search;
+mod status;
+mod store;
+mod symbol;
+mod workspace;
 
 #[allow(dead_code)]
 enum Artifacts {
@@ -59,30 +76,239 @@ impl Artifacts {
     }
 }
 
+#[derive(Clone)]
 pub struct Project {
-    pub current_file: Arc<RwLock<Option<handler::Handle>>>,
+    /// Documents currently open in the editor.
+    pub documents: store::Store,
+    /// Markdown files found on disk while indexing the workspace folders.
+    pub workspace: store::Store,
+    pub roots: Arc<RwLock<Vec<lsp_types::Url>>>,
     #[cfg(feature = "intelligence")]
     pub registry: handler::registry::Registry,
 }
 
+impl Project {
+    /// Looks up a document, preferring the editor's copy over the one indexed from disk.
+    pub async fn handle(&self, uri: &lsp_types::Url) -> Option<handler::Handle> {
+        match self.documents.get(uri).await {
+            Some(handle) => Some(handle),
+            None => self.workspace.get(uri).await,
+        }
+    }
+
+    /// Every known document, open documents shadow their indexed copy.
+    pub async fn handles(&self) -> Vec<(lsp_types::Url, handler::Handle)> {
+        let mut handles = self.documents.snapshot().await;
+        let open = handles
+            .iter()
+            .map(|(uri, _)| uri.clone())
+            .collect::<HashSet<_>>();
+
+        handles.extend(
+            self.workspace
+                .snapshot()
+                .await
+                .into_iter()
+                .filter(|(uri, _)| !open.contains(uri)),
+        );
+
+        handles
+    }
+
+    /// Finds the documents containing a block with the given hash, from where the registry last
+    /// saw it. Locations the document has moved away from since are skipped.
+    #[cfg(feature = "intelligence")]
+    pub async fn locate(
+        &self,
+        hash: &[u8; 32],
+    ) -> Vec<(lsp_types::Url, handler::Handle, handler::Block)> {
+        let mut found = Vec::new();
+
+        for (uri, start) in self.registry.locations(hash) {
+            let Some(handle) = self.handle(&uri).await else {
+                continue;
+            };
+
+            if let Some(block) = handle
+                .get_block(start.0, start.1)
+                .filter(|block| &block.metadata.hash == hash && block.start == start)
+            {
+                found.push((uri, handle, block));
+            }
+        }
+
+        found
+    }
+
+    /// Hashes of every block still referenced by an open or indexed document.
+    pub async fn hashes(&self) -> HashSet<[u8; 32]> {
+        let mut hashes = self.documents.hashes().await;
+        hashes.extend(self.workspace.hashes().await);
+        hashes
+    }
+}
+
+#[derive(Clone)]
 pub struct Backend {
     client: tower_lsp::Client,
+    config: Arc<RwLock<config::Config>>,
     artifacts: Arc<RwLock<Artifacts>>,
     project: Project,
+    /// What the client supports, set once from `initialize`.
+    capabilities: Arc<OnceLock<lsp_types::ClientCapabilities>>,
+    /// Schema the frontmatter is validated against, set once from the configuration.
+    schema: Arc<OnceLock<schema::Schema>>,
+    /// Prose rules, set once from the configuration.
+    linter: Arc<OnceLock<lint::Linter>>,
+    /// Last non-empty selection code actions were requested for, per document. Standard LSP
+    /// only tells the server about the selection through code actions.
+    selections: Arc<DashMap<lsp_types::Url, lsp_types::Range>>,
+    /// LLM summaries of selections, by the hash of the selected text.
+    summaries: Arc<DashMap<[u8; 32], String>>,
+    /// Embedding of the paragraph related links were last completed for, per document, with the
+    /// hash of its text.
+    #[cfg(feature = "intelligence")]
+    queries: Arc<DashMap<lsp_types::Url, completion::Query>>,
+    /// Serializes the changes of each open document, notifications are handled concurrently.
+    updates: Arc<DashMap<lsp_types::Url, Arc<tokio::sync::Mutex<()>>>>,
+    #[cfg(feature = "intelligence")]
+    scheduler: analysis::Scheduler,
 }
 
 impl Backend {
     pub fn new(client: tower_lsp::Client) -> Self {
         Self {
             client,
+            config: Arc::new(RwLock::new(config::Config::default())),
             artifacts: Arc::new(RwLock::new(Artifacts::Lazy)),
             project: Project {
-                current_file: Arc::new(RwLock::new(None)),
+                documents: store::Store::new(),
+                workspace: store::Store::new(),
+                roots: Arc::new(RwLock::new(Vec::new())),
                 #[cfg(feature = "intelligence")]
                 registry: handler::registry::Registry::new(),
             },
+            capabilities: Arc::new(OnceLock::new()),
+            schema: Arc::new(OnceLock::new()),
+            linter: Arc::new(OnceLock::new()),
+            selections: Arc::new(DashMap::new()),
+            summaries: Arc::new(DashMap::new()),
+            #[cfg(feature = "intelligence")]
+            queries: Arc::new(DashMap::new()),
+            updates: Arc::new(DashMap::new()),
+            #[cfg(feature = "intelligence")]
+            scheduler: analysis::Scheduler::new(),
+        }
+    }
+
+    /// Handler for the custom `marky/search` request.
+    pub async fn search(&self, params: search::SearchParams) -> Result<Vec<search::SearchResult>> {
+        match search::search(self, params).await {
+            Ok(results) => Ok(results),
+            Err(e) => {
+                error!(self, "Failed to search: {}", e);
+                Err(tower_lsp::jsonrpc::Error::internal_error())
+            }
+        }
+    }
+
+    /// Handler for the custom `marky/hoverRange` request.
+    pub async fn hover_range(
+        &self,
+        params: hover::RangeHoverParams,
+    ) -> Result<Option<lsp_types::Hover>> {
+        match hover::hover_range(self, params).await {
+            Ok(hover) => Ok(hover),
+            Err(e) => {
+                error!(self, "Failed to get range hover: {}", e);
+                Err(tower_lsp::jsonrpc::Error::internal_error())
+            }
+        }
+    }
+
+    /// Handler for the custom `marky/status` request.
+    pub async fn status(&self) -> Result<status::Status> {
+        match status::status(self).await {
+            Ok(status) => Ok(status),
+            Err(e) => {
+                error!(self, "Failed to get status: {}", e);
+                Err(tower_lsp::jsonrpc::Error::internal_error())
+            }
+        }
+    }
+
+    /// Compiles the frontmatter schema, without it the frontmatter is only checked for syntax.
+    async fn load_schema(&self, config: &config::Config) {
+        let root = self
+            .project
+            .roots
+            .read()
+            .await
+            .first()
+            .and_then(|root| root.to_file_path().ok());
+
+        match schema::Schema::load(&config.frontmatter, root.as_deref()).await {
+            Ok(Some(schema)) => {
+                let _ = self.schema.set(schema);
+            }
+            Ok(None) => {}
+            Err(e) => warn!(self, "Failed to load the frontmatter schema: {}", e),
+        }
+    }
+
+    /// Compiles the prose rules, an invalid custom rule leaves the documents unlinted.
+    async fn load_linter(&self, config: &config::Config) {
+        match lint::Linter::new(&config.lint) {
+            Ok(linter) => {
+                let _ = self.linter.set(linter);
+            }
+            Err(e) => warn!(self, "Failed to load the lint rules: {}", e),
+        }
+    }
+
+    /// Loads the on-disk registry cache, without it the registry only lives in memory. Analysis
+    /// does not wait for it, values computed before it is attached are not written to it.
+    #[cfg(feature = "intelligence")]
+    async fn attach_cache(&self) {
+        use embedding::Embedding;
+
+        let model = match self.artifacts.read().await.embedding() {
+            Ok(embedding) => embedding.model().to_string(),
+            Err(e) => {
+                warn!(self, "Failed to open the registry cache: {}", e);
+                return;
+            }
+        };
+
+        let cache = self.config.read().await.cache.clone();
+        let opened =
+            task::spawn_blocking(move || handler::registry::cache::Cache::open(&cache, &model))
+                .await
+                .map_err(anyhow::Error::from)
+                .and_then(|opened| opened);
+
+        match opened {
+            Ok(Some(cache)) => self.project.registry.attach(cache),
+            Ok(None) => {}
+            Err(e) => warn!(self, "Failed to open the registry cache: {}", e),
         }
     }
+
+    /// Extracts keywords, summaries and embeddings for the blocks of a document in the
+    /// background, superseding the pending analysis of its previous version.
+    #[cfg(feature = "intelligence")]
+    fn analyze(
+        &self,
+        uri: lsp_types::Url,
+        blocks: Vec<handler::Block>,
+        cursor: Option<(usize, usize)>,
+    ) {
+        // Blocks that were edited away no longer need the models
+        let stale = self.project.registry.place(&uri, &blocks);
+        let _ = self.project.registry.cancel(&stale);
+
+        self.scheduler.schedule(self, uri, blocks, cursor);
+    }
 }
 
 #[tower_lsp::async_trait]
@@ -91,25 +317,98 @@ impl LanguageServer for Backend {
         &self,
         info: lsp_types::InitializeParams,
     ) -> Result<lsp_types::InitializeResult> {
+        // Markdown files, and folders which may contain some
+        let renames = lsp_types::FileOperationRegistrationOptions {
+            filters: vec![
+                lsp_types::FileOperationFilter {
+                    scheme: Some("file".to_string()),
+                    pattern: lsp_types::FileOperationPattern {
+                        glob: "**/*.{md,markdown}".to_string(),
+                        matches: Some(lsp_types::FileOperationPatternKind::File),
+                        options: None,
+                    },
+                },
+                lsp_types::FileOperationFilter {
+                    scheme: Some("file".to_string()),
+                    pattern: lsp_types::FileOperationPattern {
+                        glob: "**".to_string(),
+                        matches: Some(lsp_types::FileOperationPatternKind::Folder),
+                        options: None,
+                    },
+                },
+            ],
+        };
+
         let capabilities = lsp_types::ServerCapabilities {
             hover_provider: Some(lsp_types::HoverProviderCapability::Simple(true)),
+            completion_provider: Some(lsp_types::CompletionOptions {
+                trigger_characters: Some(
+                    ["(", "[", "^", "#", "/"]
+                        .into_iter()
+                        .map(String::from)
+                        .collect(),
+                ),
+                ..Default::default()
+            }),
             text_document_sync: Some(lsp_types::TextDocumentSyncCapability::Kind(
-                lsp_types::TextDocumentSyncKind::FULL,
+                lsp_types::TextDocumentSyncKind::INCREMENTAL,
+            )),
+            document_symbol_provider: Some(lsp_types::OneOf::Left(true)),
+            workspace_symbol_provider: Some(lsp_types::OneOf::Left(true)),
+            definition_provider: Some(lsp_types::OneOf::Left(true)),
+            references_provider: Some(lsp_types::OneOf::Left(true)),
+            rename_provider: Some(lsp_types::OneOf::Right(lsp_types::RenameOptions {
+                prepare_provider: Some(true),
+                work_done_progress_options: Default::default(),
+            })),
+            workspace: Some(lsp_types::WorkspaceServerCapabilities {
+                workspace_folders: None,
+                file_operations: Some(lsp_types::WorkspaceFileOperationsServerCapabilities {
+                    will_rename: Some(renames.clone()),
+                    did_rename: Some(renames),
+                    ..Default::default()
+                }),
+            }),
+            execute_command_provider: Some(lsp_types::ExecuteCommandOptions {
+                commands: vec![hover::SUMMARIZE_SELECTION.to_string()],
+                ..Default::default()
+            }),
+            folding_range_provider: Some(lsp_types::FoldingRangeProviderCapability::Simple(true)),
+            code_action_provider: Some(lsp_types::CodeActionProviderCapability::Options(
+                lsp_types::CodeActionOptions {
+                    code_action_kinds: Some(vec![lsp_types::CodeActionKind::REFACTOR_REWRITE]),
+                    resolve_provider: Some(true),
+                    ..Default::default()
+                },
             )),
             ..Default::default()
         };
 
-        let config = match info.initialization_options {
+        *self.project.roots.write().await = workspace::roots(&info);
+        let _ = self.capabilities.set(info.capabilities.clone());
+
+        let config: config::Config = match info.initialization_options {
             Some(value) => serde_json::from_value(value)
                 .map_err(|e| tower_lsp::jsonrpc::Error::invalid_params(e.to_string()))?,
             None => Default::default(),
         };
 
-        self.artifacts.write().await.activate(config).map_err(|e| {
-            let mut error = tower_lsp::jsonrpc::Error::internal_error();
-            error.message = format!("Failed to activate artifacts: {}", e).into();
-            error
-        })?;
+        self.artifacts
+            .write()
+            .await
+            .activate(config.clone())
+            .map_err(|e| {
+                let mut error = tower_lsp::jsonrpc::Error::internal_error();
+                error.message = format!("Failed to activate artifacts: {}", e).into();
+                error
+            })?;
+
+        self.load_schema(&config).await;
+        self.load_linter(&config).await;
+        #[cfg(feature = "intelligence")]
+        self.scheduler.configure(&config.analysis);
+
+        *self.config.write().await = config;
 
         let server_info = lsp_types::ServerInfo {
             name: env!("CARGO_PKG_NAME").to_string(),
@@ -127,7 +426,26 @@ impl LanguageServer for Backend {
             .log_message(lsp_types::MessageType::INFO, "server initialized!")
             .await;
 
-        // TODO: Can index the entire workspace here
+        if let Err(e) = workspace::watch(self).await {
+            warn!(self, "Failed to watch the workspace files: {}", e);
+        }
+
+        let backend = self.clone();
+        task::spawn(async move {
+            // Opened before the workspace is indexed so its files are not analyzed again, edits
+            // made in the meantime are analyzed without it
+            #[cfg(feature = "intelligence")]
+            backend.attach_cache().await;
+
+            if let Err(e) = workspace::index(&backend).await {
+                error!(backend, "Failed to index workspace: {}", e);
+            }
+
+            // Links into files that were not indexed yet may have been reported as broken
+            if let Err(e) = diagnostic::refresh(&backend).await {
+                error!(backend, "Failed to publish diagnostics: {}", e);
+            }
+        });
     }
 
     async fn shutdown(&self) -> Result<()> {
@@ -138,11 +456,15 @@ impl LanguageServer for Backend {
 
     async fn completion(
         &self,
-        _: lsp_types::CompletionParams,
+        params: lsp_types::CompletionParams,
     ) -> Result<Option<lsp_types::CompletionResponse>> {
-        // TODO: No plan on doing completion for now
-
-        Err(tower_lsp::jsonrpc::Error::method_not_found())
+        match completion::completion(self, params).await {
+            Ok(completion) => Ok(completion),
+            Err(e) => {
+                error!(self, "Failed to get completion: {}", e);
+                Err(tower_lsp::jsonrpc::Error::internal_error())
+            }
+        }
     }
 
     async fn hover(&self, params: lsp_types::HoverParams) -> Result<Option<lsp_types::Hover>> {
@@ -155,6 +477,161 @@ impl LanguageServer for Backend {
         }
     }
 
+    async fn goto_definition(
+        &self,
+        params: lsp_types::GotoDefinitionParams,
+    ) -> Result<Option<lsp_types::GotoDefinitionResponse>> {
+        match definition::definition(self, params).await {
+            Ok(location) => Ok(location),
+            Err(e) => {
+                error!(self, "Failed to get definition: {}", e);
+                Err(tower_lsp::jsonrpc::Error::internal_error())
+            }
+        }
+    }
+
+    async fn references(
+        &self,
+        params: lsp_types::ReferenceParams,
+    ) -> Result<Option<Vec<lsp_types::Location>>> {
+        match reference::references(self, params).await {
+            Ok(locations) => Ok(locations),
+            Err(e) => {
+                error!(self, "Failed to get references: {}", e);
+                Err(tower_lsp::jsonrpc::Error::internal_error())
+            }
+        }
+    }
+
+    async fn prepare_rename(
+        &self,
+        params: lsp_types::TextDocumentPositionParams,
+    ) -> Result<Option<lsp_types::PrepareRenameResponse>> {
+        match rename::prepare_rename(self, params).await {
+            Ok(response) => Ok(response),
+            Err(e) => {
+                error!(self, "Failed to prepare rename: {}", e);
+                Err(tower_lsp::jsonrpc::Error::internal_error())
+            }
+        }
+    }
+
+    async fn rename(
+        &self,
+        params: lsp_types::RenameParams,
+    ) -> Result<Option<lsp_types::WorkspaceEdit>> {
+        match rename::rename(self, params).await {
+            Ok(edit) => Ok(edit),
+            Err(e) => {
+                error!(self, "Failed to rename: {}", e);
+                Err(tower_lsp::jsonrpc::Error::internal_error())
+            }
+        }
+    }
+
+    async fn will_rename_files(
+        &self,
+        params: lsp_types::RenameFilesParams,
+    ) -> Result<Option<lsp_types::WorkspaceEdit>> {
+        match rename::will_rename_files(self, params).await {
+            Ok(edit) => Ok(edit),
+            Err(e) => {
+                error!(self, "Failed to update links for renamed files: {}", e);
+                Err(tower_lsp::jsonrpc::Error::internal_error())
+            }
+        }
+    }
+
+    async fn did_rename_files(&self, params: lsp_types::RenameFilesParams) {
+        if let Err(e) = rename::did_rename_files(self, params).await {
+            error!(self, "Failed to move renamed files in the index: {}", e);
+        }
+    }
+
+    async fn did_change_watched_files(&self, params: lsp_types::DidChangeWatchedFilesParams) {
+        if let Err(e) = workspace::did_change_watched_files(self, params).await {
+            error!(self, "Failed to update the workspace index: {}", e);
+        }
+    }
+
+    async fn document_symbol(
+        &self,
+        params: lsp_types::DocumentSymbolParams,
+    ) -> Result<Option<lsp_types::DocumentSymbolResponse>> {
+        match symbol::document_symbol(self, params).await {
+            Ok(symbols) => Ok(symbols),
+            Err(e) => {
+                error!(self, "Failed to get document symbols: {}", e);
+                Err(tower_lsp::jsonrpc::Error::internal_error())
+            }
+        }
+    }
+
+    async fn symbol(
+        &self,
+        params: lsp_types::WorkspaceSymbolParams,
+    ) -> Result<Option<Vec<lsp_types::SymbolInformation>>> {
+        match symbol::workspace_symbol(self, params).await {
+            Ok(symbols) => Ok(symbols),
+            Err(e) => {
+                error!(self, "Failed to get workspace symbols: {}", e);
+                Err(tower_lsp::jsonrpc::Error::internal_error())
+            }
+        }
+    }
+
+    async fn folding_range(
+        &self,
+        params: lsp_types::FoldingRangeParams,
+    ) -> Result<Option<Vec<lsp_types::FoldingRange>>> {
+        match folding::folding_range(self, params).await {
+            Ok(ranges) => Ok(ranges),
+            Err(e) => {
+                error!(self, "Failed to get folding ranges: {}", e);
+                Err(tower_lsp::jsonrpc::Error::internal_error())
+            }
+        }
+    }
+
+    async fn code_action(
+        &self,
+        params: lsp_types::CodeActionParams,
+    ) -> Result<Option<lsp_types::CodeActionResponse>> {
+        match action::code_action(self, params).await {
+            Ok(actions) => Ok(actions),
+            Err(e) => {
+                error!(self, "Failed to get code actions: {}", e);
+                Err(tower_lsp::jsonrpc::Error::internal_error())
+            }
+        }
+    }
+
+    async fn execute_command(
+        &self,
+        params: lsp_types::ExecuteCommandParams,
+    ) -> Result<Option<serde_json::Value>> {
+        match hover::execute_command(self, params).await {
+            Ok(result) => Ok(result),
+            Err(e) => {
+                error!(self, "Failed to execute command: {}", e);
+                Err(tower_lsp::jsonrpc::Error::internal_error())
+            }
+        }
+    }
+
+    async fn code_action_resolve(
+        &self,
+        params: lsp_types::CodeAction,
+    ) -> Result<lsp_types::CodeAction> {
+        match action::resolve(self, params).await {
+            Ok(action) => Ok(action),
+            Err(e) => {
+                error!(self, "Failed to resolve code action: {}", e);
+                Err(tower_lsp::jsonrpc::Error::internal_error())
+            }
+        }
+    }
+
     async fn did_open(&self, params: lsp_types::DidOpenTextDocumentParams) {
         self.client
             .log_message(
@@ -174,7 +651,10 @@ impl LanguageServer for Backend {
         info!(self, "parsing file: {}", params.text_document.uri);
 
         #[cfg(feature = "intelligence")]
-        let _ = self.project.registry.garbage_collect();
+        let _ = self
+            .project
+            .registry
+            .garbage_collect(&self.project.hashes().await);
 
         let handle = match handler::Handle::new(&contents, &mut parser) {
             Ok(handle) => handle,
@@ -183,82 +663,139 @@ impl LanguageServer for Backend {
                 return;
             }
         };
+        if let Err(e) = handle.set_version(params.text_document.version) {
+            error!(self, "Failed to set the document version: {}", e);
+        }
 
         info!(self, "parsed file: {}", params.text_document.uri);
 
         #[cfg(feature = "intelligence")]
-        if let Ok(blocks) = handle.blocks.clone().read() {
-            let registry = self.project.registry.clone();
-            let heavy_blocks = blocks.clone();
-
-            task::spawn_blocking(move || {
-                let _ = registry.keyword_text(&heavy_blocks);
-                let _ = registry.summarize_text(&heavy_blocks);
-            });
-
-            let blocks = blocks.clone();
-            let registry = self.project.registry.clone();
-            let artifacts = self.artifacts.clone();
-
-            task::spawn(async move {
-                if let Ok(en) = artifacts.read().await.embedding() {
-                    let _ = registry.embed_text(&blocks, en).await;
-                }
-            });
+        if let Ok(blocks) = handle.blocks.read() {
+            self.analyze(params.text_document.uri.clone(), blocks.clone(), None);
         } else {
             error!(self, "Failed to read blocks");
         }
 
         info!(self, "[END] didOpen - {}", params.text_document.uri);
 
-        self.project.current_file.write().await.replace(handle);
+        self.project
+            .documents
+            .insert(params.text_document.uri.clone(), handle)
+            .await;
+
+        if let Err(e) = diagnostic::publish(self, params.text_document.uri).await {
+            error!(self, "Failed to publish diagnostics: {}", e);
+        }
     }
 
     async fn did_change(&self, changes: lsp_types::DidChangeTextDocumentParams) {
-        let content = changes.content_changes.first();
+        // Taken before anything else awaits, so the changes are applied in the order they came in
+        let update = self
+            .updates
+            .entry(changes.text_document.uri.clone())
+            .or_default()
+            .clone();
+        let _update = update.lock().await;
 
         info!(self, "[START] didChange - {}", changes.text_document.uri);
 
-        if let Some(content) = content {
-            let text = content.text.clone();
+        if !changes.content_changes.is_empty() {
+            // The remembered selection no longer covers the same text
+            self.selections.remove(&changes.text_document.uri);
+
             let mut parser = tree_sitter::Parser::new();
             if parser.set_language(tree_sitter_md::language()).is_err() {
                 error!(self, "Failed to set language");
                 return;
             }
 
-            let mut handle = self.project.current_file.write().await;
-            if let Some(handle) = handle.as_mut() {
-                if handle.update(&text, &mut parser).is_err() {
-                    error!(self, "Failed to update file: {}", changes.text_document.uri);
-                    return;
-                }
+            let Some(mut handle) = self.project.documents.get(&changes.text_document.uri).await
+            else {
+                warn!(self, "Unknown document: {}", changes.text_document.uri);
+                return;
+            };
+
+            let anchors = diagnostic::anchors(&handle);
+            if let Err(e) = handle.update(
+                changes.text_document.version,
+                &changes.content_changes,
+                &mut parser,
+            ) {
+                error!(
+                    self,
+                    "Failed to update file {}: {}", changes.text_document.uri, e
+                );
+                return;
+            }
+
+            // The last edit is where the cursor is
+            #[cfg(feature = "intelligence")]
+            let cursor = changes
+                .content_changes
+                .iter()
+                .rev()
+                .find_map(|change| change.range)
+                .map(|range| handle.point(range.start));
+
+            #[cfg(feature = "intelligence")]
+            if let Ok(blocks) = handle.blocks.read() {
+                self.analyze(changes.text_document.uri.clone(), blocks.clone(), cursor);
+            } else {
+                error!(self, "Failed to read blocks");
+            }
 
-                #[cfg(feature = "intelligence")]
-                if let Ok(blocks) = handle.blocks.clone().read() {
-                    let registry = self.project.registry.clone();
-                    let h_blocks = blocks.clone();
-
-                    task::spawn_blocking(move || {
-                        let _ = registry.keyword_text(&h_blocks);
-                        let _ = registry.summarize_text(&h_blocks);
-                    });
-
-                    let blocks = blocks.clone();
-                    let registry = self.project.registry.clone();
-                    let artifacts = self.artifacts.clone();
-
-                    task::spawn(async move {
-                        if let Ok(en) = artifacts.read().await.embedding() {
-                            let _ = registry.embed_text(&blocks, en).await;
-                        }
-                    });
-                } else {
-                    error!(self, "Failed to read blocks");
-                }
+            if let Err(e) = diagnostic::publish(self, changes.text_document.uri.clone()).await {
+                error!(self, "Failed to publish diagnostics: {}", e);
+            }
+
+            // Links from other documents may point at a heading that changed
+            if diagnostic::anchors(&handle) != anchors
+                && let Err(e) = diagnostic::dependents(self, &changes.text_document.uri).await
+            {
+                error!(self, "Failed to publish diagnostics: {}", e);
             }
         } else {
             warn!(self, "No content changes found");
         }
     }
+
+    async fn did_close(&self, params: lsp_types::DidCloseTextDocumentParams) {
+        info!(self, "[START] didClose - {}", params.text_document.uri);
+
+        if self
+            .project
+            .documents
+            .remove(&params.text_document.uri)
+            .await
+            .is_none()
+        {
+            warn!(self, "Unknown document: {}", params.text_document.uri);
+            return;
+        }
+
+        diagnostic::clear(self, params.text_document.uri.clone()).await;
+        self.selections.remove(&params.text_document.uri);
+        self.updates.remove(&params.text_document.uri);
+        #[cfg(feature = "intelligence")]
+        self.queries.remove(&params.text_document.uri);
+
+        #[cfg(feature = "intelligence")]
+        self.scheduler.cancel(&params.text_document.uri);
+
+        // The editor may have left unsaved changes behind, fall back to what is on disk
+        let uri = &params.text_document.uri;
+        if self.project.workspace.get(uri).await.is_some()
+            && let Err(e) = workspace::refresh(self, uri).await
+        {
+            warn!(self, "Failed to reload {}: {}", uri, e);
+        }
+
+        // Only the closed document's blocks drop out of the live set, entries shared with other
+        // open documents are retained.
+        #[cfg(feature = "intelligence")]
+        let _ = self
+            .project
+            .registry
+            .garbage_collect(&self.project.hashes().await);
+    }
 }
@@ -0,0 +1,471 @@
+use std::collections::HashMap;
+
+use unicode_segmentation::UnicodeSegmentation;
+
+use crate::handler;
+
+/// Frontmatter key holding the per-file rule levels.
+const FRONTMATTER_KEY: &str = "lint";
+
+const SENTENCE_LENGTH: &str = "sentence-length";
+const PASSIVE_VOICE: &str = "passive-voice";
+const WEASEL_WORDS: &str = "weasel-words";
+const REPEATED_WORD: &str = "repeated-word";
+
+/// Irregular past participles as a regex alternation, regular ones are recognized by their
+/// `-ed` ending.
+const IRREGULAR_PARTICIPLES: &str = "begun|bought|brought|built|caught|chosen|done|drawn|driven|\
+    eaten|fallen|felt|forgotten|found|given|gotten|held|hidden|kept|known|left|lost|made|meant|met|\
+    paid|put|read|run|said|seen|sent|set|shown|sold|spoken|taken|taught|thought|told|understood|won|\
+    written";
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct Config {
+    /// Level of any rule by id, built-in or custom, `off` disables it.
+    pub rules: HashMap<String, Level>,
+    /// Longest sentence, in words, `sentence-length` lets through.
+    pub max_sentence_length: usize,
+    /// Words `weasel-words` reports, matched whole and case insensitively.
+    pub weasel_words: Vec<String>,
+    /// Rules reporting every match of a regular expression.
+    pub custom: Vec<Custom>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            rules: HashMap::new(),
+            max_sentence_length: 30,
+            weasel_words: [
+                "very",
+                "really",
+                "quite",
+                "fairly",
+                "extremely",
+                "exceedingly",
+                "remarkably",
+                "several",
+                "various",
+                "many",
+                "mostly",
+                "largely",
+                "relatively",
+                "somewhat",
+                "significantly",
+                "substantially",
+                "basically",
+                "clearly",
+                "obviously",
+                "simply",
+            ]
+            .map(str::to_string)
+            .to_vec(),
+            custom: vec![],
+        }
+    }
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct Custom {
+    pub id: String,
+    pub pattern: String,
+    #[serde(default = "Custom::level")]
+    pub level: Level,
+    /// Reported for every match, `$1` or `$name` expand to its capture groups.
+    pub message: String,
+}
+
+impl Custom {
+    fn level() -> Level {
+        Level::Warning
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Level {
+    Off,
+    Hint,
+    Info,
+    Warning,
+    Error,
+}
+
+/// A rule violation, positions are document `(row, byte column)` points.
+#[derive(Debug, Clone)]
+pub struct Finding {
+    pub rule: String,
+    pub level: Level,
+    pub message: String,
+    pub start: (usize, usize),
+    pub end: (usize, usize),
+}
+
+enum Check {
+    SentenceLength(usize),
+    PassiveVoice(regex::Regex),
+    WeaselWords(regex::Regex),
+    RepeatedWord,
+    Pattern(regex::Regex, String),
+}
+
+struct Rule {
+    id: String,
+    level: Level,
+    check: Check,
+}
+
+/// Byte span of a match within the text and what to report about it.
+type Hit = (usize, usize, String);
+
+impl Rule {
+    fn hits(&self, text: &str) -> Vec<Hit> {
+        match &self.check {
+            Check::SentenceLength(max) => text
+                .split_sentence_bound_indices()
+                .filter_map(|(offset, sentence)| {
+                    let words = sentence.unicode_words().count();
+                    let sentence = sentence.trim_end();
+                    (words > *max).then(|| {
+                        (
+                            offset,
+                            offset + sentence.len(),
+                            format!("Sentence has {} words, keep it to {} or fewer", words, max),
+                        )
+                    })
+                })
+                .collect(),
+            Check::PassiveVoice(pattern) => pattern
+                .find_iter(text)
+                .map(|found| {
+                    (
+                        found.start(),
+                        found.end(),
+                        format!("`{}` may be passive voice", found.as_str()),
+                    )
+                })
+                .collect(),
+            Check::WeaselWords(pattern) => pattern
+                .find_iter(text)
+                .map(|found| {
+                    (
+                        found.start(),
+                        found.end(),
+                        format!("`{}` is a weasel word", found.as_str()),
+                    )
+                })
+                .collect(),
+            Check::RepeatedWord => {
+                let words = text.unicode_word_indices().collect::<Vec<_>>();
+                words
+                    .windows(2)
+                    .filter_map(|pair| match pair {
+                        [(first, word), (second, next)] => Some((*first, word, *second, next)),
+                        _ => None,
+                    })
+                    .filter(|(first, word, second, next)| {
+                        word.eq_ignore_ascii_case(next)
+                            && !word.chars().all(|c| c.is_numeric())
+                            && text
+                                .get(first + word.len()..*second)
+                                .is_some_and(|between| between.chars().all(char::is_whitespace))
+                    })
+                    .map(|(first, word, second, next)| {
+                        (
+                            first,
+                            second + next.len(),
+                            format!("Repeated word `{}`", word),
+                        )
+                    })
+                    .collect()
+            }
+            Check::Pattern(pattern, message) => pattern
+                .captures_iter(text)
+                .filter_map(|captures| {
+                    let found = captures.get(0)?;
+                    let mut expanded = String::new();
+                    captures.expand(message, &mut expanded);
+                    Some((found.start(), found.end(), expanded))
+                })
+                .collect(),
+        }
+    }
+}
+
+/// The compiled prose rules, run over every prose block of a document.
+pub struct Linter {
+    rules: Vec<Rule>,
+}
+
+impl Linter {
+    pub fn new(config: &Config) -> anyhow::Result<Self> {
+        let passive = regex::Regex::new(&format!(
+            r"(?i)\b(?:am|are|is|was|were|be|been|being)\s+(?:\w{{2,}}ed|{})\b",
+            IRREGULAR_PARTICIPLES
+        ))?;
+
+        let weasel = config
+            .weasel_words
+            .iter()
+            .map(|word| regex::escape(word))
+            .collect::<Vec<_>>()
+            .join("|");
+
+        let mut rules = vec![
+            (
+                SENTENCE_LENGTH,
+                Level::Warning,
+                Check::SentenceLength(config.max_sentence_length),
+            ),
+            (PASSIVE_VOICE, Level::Info, Check::PassiveVoice(passive)),
+            (REPEATED_WORD, Level::Warning, Check::RepeatedWord),
+        ]
+        .into_iter()
+        .map(|(id, level, check)| Rule {
+            id: id.to_string(),
+            level,
+            check,
+        })
+        .collect::<Vec<_>>();
+
+        if !weasel.is_empty() {
+            rules.push(Rule {
+                id: WEASEL_WORDS.to_string(),
+                level: Level::Info,
+                check: Check::WeaselWords(regex::Regex::new(&format!(r"(?i)\b(?:{})\b", weasel))?),
+            });
+        }
+
+        for custom in &config.custom {
+            let pattern = regex::Regex::new(&custom.pattern).map_err(|e| {
+                anyhow::anyhow!("Invalid pattern of lint rule `{}`: {}", custom.id, e)
+            })?;
+            rules.push(Rule {
+                id: custom.id.clone(),
+                level: custom.level,
+                check: Check::Pattern(pattern, custom.message.clone()),
+            });
+        }
+
+        for rule in &mut rules {
+            if let Some(level) = config.rules.get(&rule.id) {
+                rule.level = *level;
+            }
+        }
+
+        Ok(Self { rules })
+    }
+
+    /// Runs the rules over the prose of a document. The frontmatter `lint` table overrides the
+    /// configured levels, `<!-- marky-disable rule-id -->` turns rules off from there on and
+    /// `<!-- marky-enable rule-id -->` back on, without ids they apply to every rule.
+    pub fn check(&self, handle: &handler::Handle) -> anyhow::Result<Vec<Finding>> {
+        let overrides = handle
+            .frontmatter
+            .read()
+            .map_err(|_| anyhow::anyhow!("Failed while reading the frontmatter"))?
+            .as_ref()
+            .and_then(|frontmatter| frontmatter.data.get(FRONTMATTER_KEY).cloned())
+            .and_then(|levels| serde_json::from_value::<HashMap<String, Level>>(levels).ok())
+            .unwrap_or_default();
+
+        let blocks = handle
+            .blocks
+            .read()
+            .map_err(|_| anyhow::anyhow!("Failed while reading the blocks"))?
+            .clone();
+
+        let directives = directives(&blocks);
+        let text = handle.text();
+        let lines = text.lines().collect::<Vec<_>>();
+
+        let mut findings = Vec::new();
+
+        for block in blocks.iter().filter(|block| block.kind.is_prose()) {
+            // Block text is trimmed, its first line may start after the start of the block
+            let first_line = block.text.lines().next().unwrap_or_default();
+            let column = lines
+                .get(block.start.0)
+                .and_then(|line| line.get(block.start.1..))
+                .and_then(|line| line.find(first_line))
+                .map_or(block.start.1, |offset| block.start.1 + offset);
+
+            let masked = mask(&block.text);
+
+            for rule in &self.rules {
+                let level = overrides.get(&rule.id).copied().unwrap_or(rule.level);
+                if level == Level::Off {
+                    continue;
+                }
+
+                for (start, end, message) in rule.hits(&masked) {
+                    let start = locate(&block.text, block.start.0, column, start);
+                    if disabled(&directives, &rule.id, start.0) {
+                        continue;
+                    }
+
+                    findings.push(Finding {
+                        rule: rule.id.clone(),
+                        level,
+                        message,
+                        start,
+                        end: locate(&block.text, block.start.0, column, end),
+                    });
+                }
+            }
+        }
+
+        Ok(findings)
+    }
+}
+
+/// A `marky-disable` or `marky-enable` comment, no rules means every rule.
+struct Directive {
+    row: usize,
+    enable: bool,
+    rules: Vec<String>,
+}
+
+fn directives(blocks: &[handler::Block]) -> Vec<Directive> {
+    let Ok(pattern) = regex::Regex::new(r"<!--\s*marky-(disable|enable)((?:\s+[\w-]+)*)\s*-->")
+    else {
+        return vec![];
+    };
+
+    let mut directives = blocks
+        .iter()
+        .filter(|block| !matches!(block.kind, handler::BlockKind::Code { .. }))
+        .flat_map(|block| {
+            pattern.captures_iter(&block.text).map(|captures| {
+                let offset = captures.get(0).map_or(0, |found| found.start());
+                Directive {
+                    row: block.start.0
+                        + block
+                            .text
+                            .get(..offset)
+                            .unwrap_or_default()
+                            .matches('\n')
+                            .count(),
+                    enable: captures
+                        .get(1)
+                        .is_some_and(|found| found.as_str() == "enable"),
+                    rules: captures
+                        .get(2)
+                        .map(|found| {
+                            found
+                                .as_str()
+                                .split_whitespace()
+                                .map(str::to_string)
+                                .collect()
+                        })
+                        .unwrap_or_default(),
+                }
+            })
+        })
+        .collect::<Vec<_>>();
+    directives.sort_by_key(|directive| directive.row);
+
+    directives
+}
+
+/// Whether the directives before `row` leave the rule turned off.
+fn disabled(directives: &[Directive], rule: &str, row: usize) -> bool {
+    directives
+        .iter()
+        .take_while(|directive| directive.row <= row)
+        .filter(|directive| {
+            directive.rules.is_empty() || directive.rules.iter().any(|id| id == rule)
+        })
+        .last()
+        .is_some_and(|directive| !directive.enable)
+}
+
+/// Blanks out code spans, HTML comments and link destinations, keeping every byte offset.
+fn mask(text: &str) -> String {
+    let Ok(pattern) = regex::Regex::new(r"(?s)`[^`]*`|<!--.*?-->|\]\([^)]*\)") else {
+        return text.to_string();
+    };
+
+    let mut masked = String::with_capacity(text.len());
+    let mut last = 0;
+    for found in pattern.find_iter(text) {
+        masked.push_str(text.get(last..found.start()).unwrap_or_default());
+        masked.extend(found.as_str().chars().map(|c| match c {
+            '\n' => "\n".to_string(),
+            c => " ".repeat(c.len_utf8()),
+        }));
+        last = found.end();
+    }
+    masked.push_str(text.get(last..).unwrap_or_default());
+
+    masked
+}
+
+/// Document point of a byte offset into the text of a block, which is a slice of the document
+/// starting at `column` on `row`.
+fn locate(text: &str, row: usize, column: usize, offset: usize) -> (usize, usize) {
+    let before = text.get(..offset).unwrap_or(text);
+    match before.rsplit_once('\n') {
+        Some((lines, last)) => (row + lines.matches('\n').count() + 1, last.len()),
+        None => (row, column + before.len()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::ensure;
+
+    use super::{Config, Custom, Level, Linter};
+
+    #[test]
+    fn test_lint_prose() -> anyhow::Result<()> {
+        let code = [
+            "---",
+            "lint:",
+            "  weasel-words: off",
+            "---",
+            "",
+            "The report was written by the the team. It is very short.",
+            "",
+            "<!-- marky-disable passive-voice -->",
+            "",
+            "The code was reviewed. See `TODO` and TODO.",
+            "",
+        ]
+        .join("\n");
+
+        let mut parser = tree_sitter::Parser::new();
+        parser.set_language(tree_sitter_md::language())?;
+        let handle = crate::handler::Handle::new(&code, &mut parser)?;
+
+        let config = Config {
+            max_sentence_length: 7,
+            custom: vec![Custom {
+                id: "todo".to_string(),
+                pattern: "TODO".to_string(),
+                level: Level::Error,
+                message: "Resolve the $0".to_string(),
+            }],
+            ..Default::default()
+        };
+        let mut findings = Linter::new(&config)?
+            .check(&handle)?
+            .into_iter()
+            .map(|finding| (finding.rule, finding.start, finding.end))
+            .collect::<Vec<_>>();
+        findings.sort();
+
+        ensure!(
+            findings
+                == [
+                    ("passive-voice".to_string(), (5, 11), (5, 22)),
+                    ("repeated-word".to_string(), (5, 26), (5, 33)),
+                    ("sentence-length".to_string(), (5, 0), (5, 39)),
+                    ("todo".to_string(), (9, 38), (9, 42)),
+                ]
+        );
+
+        Ok(())
+    }
+}
@@ -0,0 +1,95 @@
+use std::sync::atomic::{AtomicU32, Ordering};
+
+use tower_lsp::lsp_types;
+
+static TOKEN: AtomicU32 = AtomicU32::new(0);
+
+/// A `$/progress` work-done report shown by the client while a long running task is in flight.
+///
+/// When the client does not support work-done progress or refuses to create the progress token,
+/// every report becomes a no-op.
+pub struct Progress {
+    client: tower_lsp::Client,
+    token: lsp_types::ProgressToken,
+    enabled: bool,
+}
+
+impl Progress {
+    pub async fn begin(ctx: &crate::Backend, title: impl Into<String>) -> Self {
+        let token = lsp_types::NumberOrString::String(format!(
+            "marky/{}",
+            TOKEN.fetch_add(1, Ordering::Relaxed)
+        ));
+
+        // The token may only be created when the client says it supports progress
+        let supported = ctx
+            .capabilities
+            .get()
+            .and_then(|capabilities| capabilities.window.as_ref())
+            .and_then(|window| window.work_done_progress)
+            == Some(true);
+
+        let enabled = supported
+            && ctx
+                .client
+                .send_request::<lsp_types::request::WorkDoneProgressCreate>(
+                    lsp_types::WorkDoneProgressCreateParams {
+                        token: token.clone(),
+                    },
+                )
+                .await
+                .is_ok();
+
+        let progress = Self {
+            client: ctx.client.clone(),
+            token,
+            enabled,
+        };
+
+        progress
+            .send(lsp_types::WorkDoneProgress::Begin(
+                lsp_types::WorkDoneProgressBegin {
+                    title: title.into(),
+                    cancellable: Some(false),
+                    message: None,
+                    percentage: Some(0),
+                },
+            ))
+            .await;
+
+        progress
+    }
+
+    pub async fn report(&self, message: impl Into<String>, percentage: u32) {
+        self.send(lsp_types::WorkDoneProgress::Report(
+            lsp_types::WorkDoneProgressReport {
+                cancellable: Some(false),
+                message: Some(message.into()),
+                percentage: Some(percentage.min(100)),
+            },
+        ))
+        .await;
+    }
+
+    pub async fn end(self, message: impl Into<String>) {
+        self.send(lsp_types::WorkDoneProgress::End(
+            lsp_types::WorkDoneProgressEnd {
+                message: Some(message.into()),
+            },
+        ))
+        .await;
+    }
+
+    async fn send(&self, value: lsp_types::WorkDoneProgress) {
+        if !self.enabled {
+            return;
+        }
+
+        self.client
+            .send_notification::<lsp_types::notification::Progress>(lsp_types::ProgressParams {
+                token: self.token.clone(),
+                value: lsp_types::ProgressParamsValue::WorkDone(value),
+            })
+            .await;
+    }
+}
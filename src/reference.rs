@@ -0,0 +1,77 @@
+use tower_lsp::lsp_types;
+
+/// Backlinks: on the first line of a document, every link in the workspace pointing at the
+/// document; on a heading, every link pointing at its anchor.
+pub async fn references(
+    ctx: &crate::Backend,
+    params: lsp_types::ReferenceParams,
+) -> anyhow::Result<Option<Vec<lsp_types::Location>>> {
+    let uri = params.text_document_position.text_document.uri;
+    let position = params.text_document_position.position;
+
+    let Some(handle) = ctx.project.handle(&uri).await else {
+        return Ok(None);
+    };
+
+    let (row, _) = handle.point(position);
+
+    let heading = handle
+        .heading_at(row)
+        .filter(|heading| heading.start.0 <= row && row <= heading.end.0);
+
+    let (anchor, mut locations) = match (row, heading) {
+        (0, _) => (None, vec![]),
+        (_, Some(heading)) => {
+            let declaration = lsp_types::Location {
+                uri: uri.clone(),
+                range: handle.range(heading.start, heading.end),
+            };
+
+            let locations = match params.context.include_declaration {
+                true => vec![declaration],
+                false => vec![],
+            };
+
+            (Some(heading.slug), locations)
+        }
+        _ => return Ok(None),
+    };
+
+    locations.extend(links_to(ctx, &uri, anchor.as_deref()).await?);
+
+    Ok(Some(locations))
+}
+
+/// Every link of the workspace pointing at `target`, and at `anchor` within it when given.
+pub async fn links_to(
+    ctx: &crate::Backend,
+    target: &lsp_types::Url,
+    anchor: Option<&str>,
+) -> anyhow::Result<Vec<lsp_types::Location>> {
+    let mut locations = Vec::new();
+
+    for (uri, handle) in ctx.project.handles().await {
+        let links = handle
+            .links
+            .read()
+            .map_err(|_| anyhow::anyhow!("Failed while reading the links"))?;
+
+        locations.extend(
+            links
+                .iter()
+                .filter(|link| {
+                    link.destination.as_ref().is_some_and(|destination| {
+                        destination.resolve(&uri).as_ref() == Some(target)
+                            && anchor
+                                .is_none_or(|anchor| destination.slug().as_deref() == Some(anchor))
+                    })
+                })
+                .map(|link| lsp_types::Location {
+                    uri: uri.clone(),
+                    range: handle.range(link.start, link.end),
+                }),
+        );
+    }
+
+    Ok(locations)
+}
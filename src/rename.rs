@@ -0,0 +1,459 @@
+use std::collections::HashMap;
+
+use tower_lsp::lsp_types;
+
+use crate::{
+    handler::{heading, link},
+    workspace,
+};
+
+type Changes = HashMap<lsp_types::Url, Vec<lsp_types::TextEdit>>;
+
+/// Only headings can be renamed, the placeholder is the heading text without its markers.
+pub async fn prepare_rename(
+    ctx: &crate::Backend,
+    params: lsp_types::TextDocumentPositionParams,
+) -> anyhow::Result<Option<lsp_types::PrepareRenameResponse>> {
+    let Some(handle) = ctx.project.handle(&params.text_document.uri).await else {
+        return Ok(None);
+    };
+
+    let (row, _) = handle.point(params.position);
+
+    Ok(handle
+        .heading_at(row)
+        .filter(|heading| heading.start.0 <= row && row <= heading.end.0)
+        .map(
+            |heading| lsp_types::PrepareRenameResponse::RangeWithPlaceholder {
+                range: handle.range(heading.content.0, heading.content.1),
+                placeholder: heading.text,
+            },
+        ))
+}
+
+/// Renames the heading under the cursor and rewrites every link in the workspace pointing at an
+/// anchor of the document that changes as a result.
+pub async fn rename(
+    ctx: &crate::Backend,
+    params: lsp_types::RenameParams,
+) -> anyhow::Result<Option<lsp_types::WorkspaceEdit>> {
+    let uri = params.text_document_position.text_document.uri;
+    let new_name = params.new_name.trim();
+
+    let Some(handle) = ctx.project.handle(&uri).await else {
+        return Ok(None);
+    };
+
+    let (row, _) = handle.point(params.text_document_position.position);
+
+    let headings = handle
+        .headings
+        .read()
+        .map_err(|_| anyhow::anyhow!("Failed while reading the headings"))?
+        .clone();
+
+    let Some(index) = headings
+        .iter()
+        .position(|heading| heading.start.0 <= row && row <= heading.end.0)
+    else {
+        return Ok(None);
+    };
+
+    if new_name.is_empty() {
+        anyhow::bail!("A heading can not be renamed to an empty text");
+    }
+
+    // Duplicate headings are numbered in order, renaming one can shift the anchors of the others
+    let slugs = heading::slugs(
+        headings
+            .iter()
+            .enumerate()
+            .map(|(i, heading)| match i == index {
+                true => new_name,
+                false => heading.text.as_str(),
+            }),
+    );
+    let renamed = headings
+        .iter()
+        .zip(&slugs)
+        .filter(|(heading, slug)| &heading.slug != *slug)
+        .map(|(heading, slug)| (heading.slug.clone(), slug.clone()))
+        .collect::<HashMap<_, _>>();
+
+    let mut changes = Changes::new();
+
+    if let Some(heading) = headings
+        .get(index)
+        .filter(|heading| heading.text != new_name)
+    {
+        changes
+            .entry(uri.clone())
+            .or_default()
+            .push(lsp_types::TextEdit {
+                range: handle.range(heading.content.0, heading.content.1),
+                new_text: new_name.to_string(),
+            });
+    }
+
+    for (source, handle) in ctx.project.handles().await {
+        let links = handle
+            .links
+            .read()
+            .map_err(|_| anyhow::anyhow!("Failed while reading the links"))?;
+
+        for destination in links.iter().filter_map(|link| link.destination.as_ref()) {
+            let Some(anchor) = destination.slug() else {
+                continue;
+            };
+            let Some(slug) = renamed.get(&anchor) else {
+                continue;
+            };
+            if destination.anchor() == Some(slug.as_str())
+                || !destination
+                    .resolve(&source)
+                    .is_some_and(|target| same(&target, &uri))
+            {
+                continue;
+            }
+
+            // Destinations never span lines, the anchor follows the path and the `#`
+            let start = (
+                destination.start.0,
+                destination.start.1 + destination.path().len() + 1,
+            );
+
+            changes
+                .entry(source.clone())
+                .or_default()
+                .push(lsp_types::TextEdit {
+                    range: handle.range(start, destination.end),
+                    new_text: slug.clone(),
+                });
+        }
+    }
+
+    Ok(Some(lsp_types::WorkspaceEdit {
+        changes: Some(changes),
+        ..Default::default()
+    }))
+}
+
+/// Rewrites the relative links affected by moving files or folders: links pointing into the
+/// moved paths, and links inside moved documents pointing elsewhere.
+pub async fn will_rename_files(
+    ctx: &crate::Backend,
+    params: lsp_types::RenameFilesParams,
+) -> anyhow::Result<Option<lsp_types::WorkspaceEdit>> {
+    let files = renames(&params)?;
+
+    let mut changes = Changes::new();
+
+    for (source, handle) in ctx.project.handles().await {
+        let links = handle
+            .links
+            .read()
+            .map_err(|_| anyhow::anyhow!("Failed while reading the links"))?;
+
+        let base = moved(&source, &files);
+
+        for destination in links.iter().filter_map(|link| link.destination.as_ref()) {
+            if destination.path().is_empty() {
+                continue;
+            }
+            let Some(target) = destination.resolve(&source) else {
+                continue;
+            };
+
+            let moved_target = moved(&target, &files);
+            if base.is_none() && moved_target.is_none() {
+                continue;
+            }
+
+            let (base, target) = (
+                base.as_ref().unwrap_or(&source),
+                moved_target.as_ref().unwrap_or(&target),
+            );
+
+            // The link still leads there, e.g. both ends moved along with their folder
+            if link::resolve(base, destination.path()).is_some_and(|path| same(&path, target)) {
+                continue;
+            }
+
+            let Some(path) = relative(base, target, destination.path()) else {
+                continue;
+            };
+
+            changes
+                .entry(source.clone())
+                .or_default()
+                .push(lsp_types::TextEdit {
+                    range: handle.range(
+                        destination.start,
+                        (
+                            destination.start.0,
+                            destination.start.1 + destination.path().len(),
+                        ),
+                    ),
+                    new_text: path,
+                });
+        }
+    }
+
+    Ok(Some(lsp_types::WorkspaceEdit {
+        changes: Some(changes),
+        ..Default::default()
+    }))
+}
+
+/// Moves the indexed copies of renamed files to their new location. They are reloaded from disk,
+/// the rename usually comes with link edits.
+pub async fn did_rename_files(
+    ctx: &crate::Backend,
+    params: lsp_types::RenameFilesParams,
+) -> anyhow::Result<()> {
+    let files = renames(&params)?;
+
+    let mut failed = Vec::new();
+    for (uri, _) in ctx.project.workspace.snapshot().await {
+        let Some(target) = moved(&uri, &files) else {
+            continue;
+        };
+
+        ctx.project.workspace.remove(&uri).await;
+        if let Err(e) = workspace::refresh(ctx, &target).await {
+            failed.push(format!("{} ({})", target, e));
+        }
+    }
+
+    if !failed.is_empty() {
+        anyhow::bail!("Failed to reload {}", failed.join(", "));
+    }
+
+    Ok(())
+}
+
+fn renames(
+    params: &lsp_types::RenameFilesParams,
+) -> anyhow::Result<Vec<(lsp_types::Url, lsp_types::Url)>> {
+    params
+        .files
+        .iter()
+        .map(|file| {
+            Ok((
+                lsp_types::Url::parse(&file.old_uri)?,
+                lsp_types::Url::parse(&file.new_uri)?,
+            ))
+        })
+        .collect()
+}
+
+/// Where `uri` ends up once the files are renamed, `None` when it does not move.
+fn moved(
+    uri: &lsp_types::Url,
+    files: &[(lsp_types::Url, lsp_types::Url)],
+) -> Option<lsp_types::Url> {
+    files.iter().find_map(|(old, new)| {
+        if same(uri, old) {
+            return Some(new.clone());
+        }
+
+        // Renamed folders move everything below them
+        let rest = uri
+            .to_file_path()
+            .ok()?
+            .strip_prefix(old.to_file_path().ok()?)
+            .ok()?
+            .to_path_buf();
+        lsp_types::Url::from_file_path(new.to_file_path().ok()?.join(rest)).ok()
+    })
+}
+
+/// Whether two URIs name the same file, however their paths are percent-encoded.
+fn same(a: &lsp_types::Url, b: &lsp_types::Url) -> bool {
+    match (a.to_file_path(), b.to_file_path()) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => a == b,
+    }
+}
+
+/// The path from `base` to `target`, keeping the `./` prefix when the original path had one.
+fn relative(base: &lsp_types::Url, target: &lsp_types::Url, original: &str) -> Option<String> {
+    let path = match base.make_relative(target)? {
+        // A document linking to itself by name
+        path if path.is_empty() => target.path_segments()?.next_back()?.to_string(),
+        path => path,
+    };
+
+    match original.starts_with("./") && !path.starts_with("../") {
+        true => Some(format!("./{}", path)),
+        false => Some(path),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::ensure;
+    use tower_lsp::lsp_types;
+
+    #[tokio::test]
+    async fn test_rename_non_ascii() -> anyhow::Result<()> {
+        let (service, _) = tower_lsp::LspService::new(crate::Backend::new);
+        let ctx = service.inner();
+
+        let mut parser = tree_sitter::Parser::new();
+        parser.set_language(tree_sitter_md::language())?;
+
+        let target = lsp_types::Url::parse("file:///notes/a.md")?;
+        let source = lsp_types::Url::parse("file:///notes/b.md")?;
+        let documents = [
+            (&target, "# Café ☕\n\n## Übersicht\n"),
+            (
+                &source,
+                "Voilà 😀 [see](a.md#übersicht) and [up](a.md#Übersicht)\n",
+            ),
+        ];
+        for (uri, text) in documents {
+            let handle = crate::handler::Handle::new(text, &mut parser)?;
+            ctx.project.documents.insert(uri.clone(), handle).await;
+        }
+
+        let ranges = |edit: Option<lsp_types::WorkspaceEdit>, uri: &lsp_types::Url| {
+            edit.and_then(|edit| edit.changes)
+                .and_then(|mut changes| changes.remove(uri))
+                .unwrap_or_default()
+                .into_iter()
+                .map(|edit| {
+                    let lsp_types::Range { start, end } = edit.range;
+                    ((start.line, start.character, end.character), edit.new_text)
+                })
+                .collect::<Vec<_>>()
+        };
+
+        let edit = super::rename(
+            ctx,
+            lsp_types::RenameParams {
+                text_document_position: lsp_types::TextDocumentPositionParams {
+                    text_document: lsp_types::TextDocumentIdentifier {
+                        uri: target.clone(),
+                    },
+                    position: lsp_types::Position::new(2, 4),
+                },
+                new_name: "Überblick".to_string(),
+                work_done_progress_params: Default::default(),
+            },
+        )
+        .await?;
+
+        // Both anchors match regardless of case, columns count UTF-16 code units
+        ensure!(
+            ranges(edit, &source)
+                == [
+                    ((0, 20, 29), "überblick".to_string()),
+                    ((0, 45, 54), "überblick".to_string()),
+                ]
+        );
+
+        let edit = super::will_rename_files(
+            ctx,
+            lsp_types::RenameFilesParams {
+                files: vec![lsp_types::FileRename {
+                    old_uri: target.to_string(),
+                    new_uri: "file:///notes/docs/a.md".to_string(),
+                }],
+            },
+        )
+        .await?;
+
+        ensure!(
+            ranges(edit, &source)
+                == [
+                    ((0, 15, 19), "docs/a.md".to_string()),
+                    ((0, 40, 44), "docs/a.md".to_string()),
+                ]
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_rename_encoded_paths() -> anyhow::Result<()> {
+        let (service, _) = tower_lsp::LspService::new(crate::Backend::new);
+        let ctx = service.inner();
+
+        let mut parser = tree_sitter::Parser::new();
+        parser.set_language(tree_sitter_md::language())?;
+
+        let target = lsp_types::Url::parse("file:///notes/my%20caf%C3%A9.md")?;
+        let source = lsp_types::Url::parse("file:///notes/index.md")?;
+        let documents = [
+            (&target, "# Note\n\n[index](index.md)\n"),
+            (
+                &source,
+                "[note](my%20caf%c3%a9.md#note) and [same](<my café.md>)\n",
+            ),
+        ];
+        for (uri, text) in documents {
+            let handle = crate::handler::Handle::new(text, &mut parser)?;
+            ctx.project.documents.insert(uri.clone(), handle).await;
+        }
+
+        let edits = |edit: Option<lsp_types::WorkspaceEdit>| {
+            edit.and_then(|edit| edit.changes)
+                .unwrap_or_default()
+                .into_iter()
+                .flat_map(|(uri, edits)| {
+                    edits
+                        .into_iter()
+                        .map(move |edit| (uri.to_string(), edit.new_text))
+                })
+                .collect::<Vec<_>>()
+        };
+        let rename = |old: &str, new: &str| lsp_types::RenameFilesParams {
+            files: vec![lsp_types::FileRename {
+                old_uri: old.to_string(),
+                new_uri: new.to_string(),
+            }],
+        };
+
+        // The links encode the name differently from the client, they are still found
+        let edit = super::will_rename_files(
+            ctx,
+            rename(target.as_str(), "file:///notes/your%20caf%C3%A9.md"),
+        )
+        .await?;
+        let renamed = edits(edit);
+        ensure!(
+            renamed
+                == [
+                    (source.to_string(), "your%20caf%C3%A9.md".to_string()),
+                    (source.to_string(), "your%20caf%C3%A9.md".to_string()),
+                ],
+            "{:?}",
+            renamed
+        );
+
+        // Moving the folder keeps the links between its files as they are
+        let edit =
+            super::will_rename_files(ctx, rename("file:///notes", "file:///archive")).await?;
+        ensure!(edits(edit).is_empty());
+
+        // Renaming a heading to its own text changes nothing
+        let edit = super::rename(
+            ctx,
+            lsp_types::RenameParams {
+                text_document_position: lsp_types::TextDocumentPositionParams {
+                    text_document: lsp_types::TextDocumentIdentifier {
+                        uri: target.clone(),
+                    },
+                    position: lsp_types::Position::new(0, 3),
+                },
+                new_name: "Note".to_string(),
+                work_done_progress_params: Default::default(),
+            },
+        )
+        .await?;
+        ensure!(edits(edit).is_empty());
+
+        Ok(())
+    }
+}
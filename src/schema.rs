@@ -0,0 +1,236 @@
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Clone, Default, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct Config {
+    /// JSON Schema the frontmatter of every document is validated against.
+    pub schema: Option<Source>,
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(untagged)]
+pub enum Source {
+    /// Path of a JSON file, relative to the first workspace folder.
+    Path(PathBuf),
+    Inline(serde_json::Value),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Code {
+    MissingKey,
+    UnknownKey,
+    WrongType,
+    InvalidValue,
+}
+
+impl Code {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::MissingKey => "missing-key",
+            Self::UnknownKey => "unknown-key",
+            Self::WrongType => "wrong-type",
+            Self::InvalidValue => "invalid-value",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Violation {
+    pub code: Code,
+    /// The top level key the violation is about, `None` when it is about the frontmatter as a
+    /// whole, e.g. a missing key.
+    pub key: Option<String>,
+    pub message: String,
+}
+
+/// A top level property described by the schema.
+#[derive(Debug, Clone)]
+pub struct Property {
+    pub name: String,
+    pub description: Option<String>,
+    pub types: Vec<String>,
+    /// Allowed values, empty when any value of the right type goes.
+    pub values: Vec<serde_json::Value>,
+    pub required: bool,
+}
+
+/// The compiled frontmatter schema.
+pub struct Schema {
+    raw: serde_json::Value,
+    compiled: jsonschema::JSONSchema,
+}
+
+impl Schema {
+    /// Loads and compiles the configured schema, `None` when there is none.
+    pub async fn load(config: &Config, root: Option<&Path>) -> anyhow::Result<Option<Self>> {
+        let raw = match &config.schema {
+            None => return Ok(None),
+            Some(Source::Inline(raw)) => raw.clone(),
+            Some(Source::Path(path)) => {
+                let path = match root {
+                    Some(root) => root.join(path),
+                    None => path.clone(),
+                };
+                serde_json::from_str(&tokio::fs::read_to_string(&path).await?)?
+            }
+        };
+
+        let compiled = jsonschema::JSONSchema::compile(&raw)
+            .map_err(|e| anyhow::anyhow!("Invalid frontmatter schema: {}", e))?;
+
+        Ok(Some(Self { raw, compiled }))
+    }
+
+    pub fn validate(&self, data: &serde_json::Value) -> Vec<Violation> {
+        let Err(errors) = self.compiled.validate(data) else {
+            return vec![];
+        };
+
+        let mut violations = Vec::new();
+
+        for error in errors {
+            let path = error.instance_path.clone().into_vec();
+            let key = path.first().cloned();
+            let name = |property: &str| {
+                path.iter()
+                    .map(String::as_str)
+                    .chain([property])
+                    .collect::<Vec<_>>()
+                    .join(".")
+            };
+
+            match &error.kind {
+                jsonschema::error::ValidationErrorKind::Required { property } => {
+                    let property = property.as_str().unwrap_or_default();
+                    violations.push(Violation {
+                        code: Code::MissingKey,
+                        key,
+                        message: format!("Missing required key `{}`", name(property)),
+                    });
+                }
+                jsonschema::error::ValidationErrorKind::AdditionalProperties { unexpected } => {
+                    violations.extend(unexpected.iter().map(|property| Violation {
+                        code: Code::UnknownKey,
+                        key: key.clone().or_else(|| Some(property.clone())),
+                        message: format!("Unknown key `{}`", name(property)),
+                    }));
+                }
+                jsonschema::error::ValidationErrorKind::Type { .. } => {
+                    violations.push(Violation {
+                        code: Code::WrongType,
+                        message: format!("`{}`: {}", path.join("."), error),
+                        key,
+                    });
+                }
+                _ => violations.push(Violation {
+                    code: Code::InvalidValue,
+                    message: match path.is_empty() {
+                        true => error.to_string(),
+                        false => format!("`{}`: {}", path.join("."), error),
+                    },
+                    key,
+                }),
+            }
+        }
+
+        violations
+    }
+
+    /// The top level properties, sorted by name.
+    pub fn properties(&self) -> Vec<Property> {
+        let required = self
+            .raw
+            .get("required")
+            .and_then(serde_json::Value::as_array)
+            .map(|required| {
+                required
+                    .iter()
+                    .filter_map(serde_json::Value::as_str)
+                    .collect::<Vec<_>>()
+            })
+            .unwrap_or_default();
+
+        let Some(properties) = self
+            .raw
+            .get("properties")
+            .and_then(serde_json::Value::as_object)
+        else {
+            return vec![];
+        };
+
+        properties
+            .iter()
+            .map(|(name, property)| Property {
+                name: name.clone(),
+                description: property
+                    .get("description")
+                    .and_then(serde_json::Value::as_str)
+                    .map(str::to_string),
+                types: match property.get("type") {
+                    Some(serde_json::Value::String(kind)) => vec![kind.clone()],
+                    Some(serde_json::Value::Array(kinds)) => kinds
+                        .iter()
+                        .filter_map(serde_json::Value::as_str)
+                        .map(str::to_string)
+                        .collect(),
+                    _ => vec![],
+                },
+                values: property
+                    .get("enum")
+                    .and_then(serde_json::Value::as_array)
+                    .cloned()
+                    .unwrap_or_default(),
+                required: required.contains(&name.as_str()),
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::ensure;
+
+    use super::{Code, Config, Schema, Source};
+
+    #[tokio::test]
+    async fn test_validate_frontmatter() -> anyhow::Result<()> {
+        let config = Config {
+            schema: Some(Source::Inline(serde_json::json!({
+                "type": "object",
+                "required": ["title", "owner"],
+                "additionalProperties": false,
+                "properties": {
+                    "title": { "type": "string" },
+                    "owner": { "type": "string" },
+                    "tags": { "type": "array", "items": { "type": "string" } },
+                }
+            }))),
+        };
+        let schema = Schema::load(&config, None)
+            .await?
+            .ok_or_else(|| anyhow::anyhow!("No schema"))?;
+
+        let violations = schema.validate(&serde_json::json!({
+            "title": "Notes",
+            "tags": "rust",
+            "draft": true,
+        }));
+        let mut found = violations
+            .iter()
+            .map(|violation| (violation.code, violation.key.as_deref()))
+            .collect::<Vec<_>>();
+        found.sort_by_key(|(code, _)| code.as_str());
+
+        ensure!(
+            found
+                == [
+                    (Code::MissingKey, None),
+                    (Code::UnknownKey, Some("draft")),
+                    (Code::WrongType, Some("tags")),
+                ]
+        );
+        ensure!(schema.properties().len() == 3);
+
+        Ok(())
+    }
+}
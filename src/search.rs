@@ -0,0 +1,71 @@
+use tower_lsp::lsp_types;
+
+#[cfg(feature = "intelligence")]
+use crate::embedding::Embedding;
+
+/// Parameters of the `marky/search` request.
+#[derive(Debug, Clone, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SearchParams {
+    pub query: String,
+    #[serde(default = "default_limit")]
+    pub limit: usize,
+}
+
+fn default_limit() -> usize {
+    10
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SearchResult {
+    pub uri: lsp_types::Url,
+    pub range: lsp_types::Range,
+    /// Cosine similarity between the query and the block.
+    pub score: f32,
+    pub text: String,
+}
+
+/// Embeds the query and returns the `limit` blocks of the workspace closest to it.
+#[cfg(feature = "intelligence")]
+pub async fn search(
+    ctx: &crate::Backend,
+    params: SearchParams,
+) -> anyhow::Result<Vec<SearchResult>> {
+    let query = ctx
+        .artifacts
+        .read()
+        .await
+        .embedding()?
+        .embed(params.query)
+        .await?;
+
+    let hits = ctx.project.registry.nearest(&query, params.limit).await?;
+
+    let mut results = Vec::with_capacity(hits.len());
+    for (hash, score) in hits {
+        results.extend(
+            ctx.project
+                .locate(&hash)
+                .await
+                .into_iter()
+                .map(|(uri, handle, block)| SearchResult {
+                    uri,
+                    range: handle.range(block.start, block.end),
+                    score,
+                    text: block.text,
+                }),
+        );
+    }
+    results.truncate(params.limit);
+
+    Ok(results)
+}
+
+#[cfg(not(feature = "intelligence"))]
+pub async fn search(
+    _ctx: &crate::Backend,
+    _params: SearchParams,
+) -> anyhow::Result<Vec<SearchResult>> {
+    anyhow::bail!("semantic search requires the `intelligence` feature");
+}
@@ -0,0 +1,24 @@
+#[cfg(feature = "intelligence")]
+use crate::handler;
+
+/// Result of the `marky/status` request.
+#[derive(Debug, Clone, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Status {
+    /// Documents open in the editor.
+    pub documents: usize,
+    /// Markdown files indexed from the workspace folders.
+    pub indexed: usize,
+    /// Queue depth, model load state and cache hit rates of the background analysis.
+    #[cfg(feature = "intelligence")]
+    pub analysis: handler::registry::Status,
+}
+
+pub async fn status(ctx: &crate::Backend) -> anyhow::Result<Status> {
+    Ok(Status {
+        documents: ctx.project.documents.snapshot().await.len(),
+        indexed: ctx.project.workspace.snapshot().await.len(),
+        #[cfg(feature = "intelligence")]
+        analysis: ctx.project.registry.status()?,
+    })
+}
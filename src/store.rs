@@ -0,0 +1,59 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+
+use tokio::sync::RwLock;
+use tower_lsp::lsp_types;
+
+use crate::handler;
+
+/// Per-URI store of parsed documents.
+///
+/// Every handler that needs a document goes through the store, so multiple open files (splits,
+/// tabs) never see each other's blocks.
+#[derive(Clone, Default)]
+pub struct Store {
+    documents: Arc<RwLock<HashMap<lsp_types::Url, handler::Handle>>>,
+}
+
+impl Store {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub async fn insert(&self, uri: lsp_types::Url, handle: handler::Handle) {
+        self.documents.write().await.insert(uri, handle);
+    }
+
+    /// Returns a handle to the document, the handle shares its state with the one in the store.
+    pub async fn get(&self, uri: &lsp_types::Url) -> Option<handler::Handle> {
+        self.documents.read().await.get(uri).cloned()
+    }
+
+    pub async fn remove(&self, uri: &lsp_types::Url) -> Option<handler::Handle> {
+        self.documents.write().await.remove(uri)
+    }
+
+    /// A point-in-time copy of every document in the store.
+    pub async fn snapshot(&self) -> Vec<(lsp_types::Url, handler::Handle)> {
+        self.documents
+            .read()
+            .await
+            .iter()
+            .map(|(uri, handle)| (uri.clone(), handle.clone()))
+            .collect()
+    }
+
+    /// Hashes of every block that is still part of some document in the store.
+    pub async fn hashes(&self) -> HashSet<[u8; 32]> {
+        let documents = self.documents.read().await;
+
+        let mut hashes = HashSet::new();
+        for handle in documents.values() {
+            if let Ok(blocks) = handle.blocks.read() {
+                hashes.extend(blocks.iter().map(|block| block.metadata.hash));
+            }
+        }
+
+        hashes
+    }
+}
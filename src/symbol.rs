@@ -0,0 +1,188 @@
+use std::iter::Peekable;
+
+use tower_lsp::lsp_types;
+
+use crate::handler::{self, Heading};
+
+#[cfg(feature = "intelligence")]
+use crate::embedding::Embedding;
+
+const WORKSPACE_SYMBOL_LIMIT: usize = 128;
+
+/// Minimum cosine similarity for a heading to match a phrase without matching it literally.
+#[cfg(feature = "intelligence")]
+const SEMANTIC_THRESHOLD: f32 = 0.5;
+
+/// Builds the outline of a document, nesting every heading under the closest preceding heading
+/// of a lower level.
+pub async fn document_symbol(
+    ctx: &crate::Backend,
+    params: lsp_types::DocumentSymbolParams,
+) -> anyhow::Result<Option<lsp_types::DocumentSymbolResponse>> {
+    let Some(handle) = ctx.project.handle(&params.text_document.uri).await else {
+        return Ok(None);
+    };
+
+    let headings = handle
+        .headings
+        .read()
+        .map_err(|_| anyhow::anyhow!("Failed while reading the headings"))?
+        .clone();
+    let ends = handler::heading::section_ends(&headings, handle.end());
+
+    let mut sections = headings.into_iter().zip(ends).peekable();
+    let symbols = nest(&handle, &mut sections, 0);
+
+    Ok(Some(lsp_types::DocumentSymbolResponse::Nested(symbols)))
+}
+
+fn nest(
+    handle: &handler::Handle,
+    sections: &mut Peekable<impl Iterator<Item = (Heading, (usize, usize))>>,
+    level: usize,
+) -> Vec<lsp_types::DocumentSymbol> {
+    let mut symbols = Vec::new();
+
+    while let Some((heading, end)) = sections.next_if(|(heading, _)| heading.level > level) {
+        let children = nest(handle, sections, heading.level);
+
+        #[allow(deprecated)]
+        symbols.push(lsp_types::DocumentSymbol {
+            name: heading.text,
+            detail: Some(format!("h{}", heading.level)),
+            kind: lsp_types::SymbolKind::STRING,
+            tags: None,
+            deprecated: None,
+            range: handle.range(heading.start, end),
+            selection_range: handle.range(heading.content.0, heading.content.1),
+            children: (!children.is_empty()).then_some(children),
+        });
+    }
+
+    symbols
+}
+
+/// Searches the headings of every known document, fuzzy matching the query against the heading
+/// text. Phrase queries are additionally ranked by embedding similarity.
+pub async fn workspace_symbol(
+    ctx: &crate::Backend,
+    params: lsp_types::WorkspaceSymbolParams,
+) -> anyhow::Result<Option<Vec<lsp_types::SymbolInformation>>> {
+    let query = params.query.trim();
+
+    #[cfg(feature = "intelligence")]
+    let embedding = match query.contains(char::is_whitespace) {
+        true => match ctx.artifacts.read().await.embedding() {
+            Ok(engine) => engine.embed(query.to_string()).await.ok(),
+            Err(_) => None,
+        },
+        false => None,
+    };
+    #[cfg(not(feature = "intelligence"))]
+    let embedding: Option<Vec<f32>> = None;
+
+    let mut ranked = Vec::new();
+
+    for (uri, handle) in ctx.project.handles().await {
+        let headings = handle
+            .headings
+            .read()
+            .map_err(|_| anyhow::anyhow!("Failed while reading the headings"))?
+            .clone();
+
+        for heading in headings {
+            let literal = fuzzy(query, &heading.text);
+
+            let semantic = semantic(ctx, &heading, embedding.as_deref());
+
+            if literal.is_none() && semantic.is_none() {
+                continue;
+            }
+
+            let score = literal.unwrap_or_default() + semantic.unwrap_or_default();
+
+            #[allow(deprecated)]
+            let symbol = lsp_types::SymbolInformation {
+                name: heading.text,
+                kind: lsp_types::SymbolKind::STRING,
+                tags: None,
+                deprecated: None,
+                location: lsp_types::Location {
+                    uri: uri.clone(),
+                    range: handle.range(heading.content.0, heading.content.1),
+                },
+                container_name: uri
+                    .path_segments()
+                    .and_then(|mut segments| segments.next_back())
+                    .map(str::to_string),
+            };
+
+            ranked.push((score, symbol));
+        }
+    }
+
+    ranked.sort_by(|(a, _), (b, _)| b.total_cmp(a));
+    ranked.truncate(WORKSPACE_SYMBOL_LIMIT);
+
+    Ok(Some(ranked.into_iter().map(|(_, symbol)| symbol).collect()))
+}
+
+/// Cosine similarity between the heading and the embedded query, when close enough to count as a
+/// match.
+#[cfg(feature = "intelligence")]
+fn semantic(ctx: &crate::Backend, heading: &Heading, embedding: Option<&[f32]>) -> Option<f32> {
+    let hash = blake3::hash(heading.text.as_bytes());
+
+    ctx.project
+        .registry
+        .similarity(hash.as_bytes(), embedding?)
+        .filter(|score| *score >= SEMANTIC_THRESHOLD)
+}
+
+#[cfg(not(feature = "intelligence"))]
+fn semantic(_ctx: &crate::Backend, _heading: &Heading, _embedding: Option<&[f32]>) -> Option<f32> {
+    None
+}
+
+/// Case-insensitive subsequence match scored in `(0, 1]`, consecutive characters and characters
+/// at the start of a word weigh more.
+fn fuzzy(query: &str, candidate: &str) -> Option<f32> {
+    let query = query
+        .to_lowercase()
+        .chars()
+        .filter(|c| !c.is_whitespace())
+        .collect::<Vec<_>>();
+
+    if query.is_empty() {
+        return Some(1.0);
+    }
+
+    let mut score = 0;
+    let mut matched = 0;
+    let mut consecutive = false;
+    let mut previous: Option<char> = None;
+
+    for c in candidate.to_lowercase().chars() {
+        if query.get(matched) == Some(&c) {
+            score += 1;
+            if consecutive {
+                score += 2;
+            }
+            if previous.is_none_or(|previous| !previous.is_alphanumeric()) {
+                score += 2;
+            }
+            matched += 1;
+            consecutive = true;
+        } else {
+            consecutive = false;
+        }
+        previous = Some(c);
+    }
+
+    if matched < query.len() {
+        return None;
+    }
+
+    #[allow(clippy::as_conversions)]
+    Some((score as f32 / (query.len() * 5) as f32).min(1.0))
+}
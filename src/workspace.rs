@@ -0,0 +1,270 @@
+use std::path::{Path, PathBuf};
+
+use globset::{Glob, GlobSet, GlobSetBuilder};
+use tokio::task;
+use tower_lsp::lsp_types;
+
+use crate::{diagnostic, handler, progress};
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct Config {
+    /// Globs, relative to the workspace folder, of the files to index.
+    pub include: Vec<String>,
+    /// Globs, relative to the workspace folder, of the files to skip even when included.
+    pub exclude: Vec<String>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            include: vec!["**/*.md".to_string(), "**/*.markdown".to_string()],
+            exclude: vec![],
+        }
+    }
+}
+
+/// Extracts the workspace folders the client asked us to serve.
+pub fn roots(info: &lsp_types::InitializeParams) -> Vec<lsp_types::Url> {
+    match &info.workspace_folders {
+        Some(folders) if !folders.is_empty() => {
+            folders.iter().map(|folder| folder.uri.clone()).collect()
+        }
+        #[allow(deprecated)]
+        _ => info.root_uri.iter().cloned().collect(),
+    }
+}
+
+/// Parses every Markdown file in the workspace folders into the workspace store and hands their
+/// blocks to the registry.
+pub async fn index(ctx: &crate::Backend) -> anyhow::Result<()> {
+    let roots = ctx
+        .project
+        .roots
+        .read()
+        .await
+        .iter()
+        .filter_map(|root| root.to_file_path().ok())
+        .collect::<Vec<_>>();
+    let config = ctx.config.read().await.workspace.clone();
+
+    let paths = task::spawn_blocking(move || discover(&roots, &config)).await??;
+
+    let progress = progress::Progress::begin(ctx, "Indexing workspace").await;
+    let total = paths.len();
+
+    for (index, path) in paths.iter().enumerate() {
+        #[allow(clippy::as_conversions)]
+        let percentage = (index * 100 / total.max(1)) as u32;
+        progress
+            .report(format!("{}/{} files", index + 1, total), percentage)
+            .await;
+
+        match load(path).await {
+            Ok((uri, handle)) => {
+                #[cfg(feature = "intelligence")]
+                analyze(ctx, &uri, &handle).await;
+
+                ctx.project.workspace.insert(uri, handle).await;
+            }
+            Err(e) => warn!(ctx, "Failed to index {}: {}", path.display(), e),
+        }
+    }
+
+    progress.end(format!("Indexed {} files", total)).await;
+    info!(ctx, "indexed {} files", total);
+
+    Ok(())
+}
+
+/// Reloads a single indexed file from disk, e.g. once the editor stops tracking its contents.
+pub async fn refresh(ctx: &crate::Backend, uri: &lsp_types::Url) -> anyhow::Result<()> {
+    let path = uri
+        .to_file_path()
+        .map_err(|_| anyhow::anyhow!("Not a file path: {}", uri))?;
+
+    let (uri, handle) = load(&path).await?;
+
+    #[cfg(feature = "intelligence")]
+    analyze(ctx, &uri, &handle).await;
+
+    ctx.project.workspace.insert(uri, handle).await;
+
+    Ok(())
+}
+
+/// Hands the blocks of a file read from disk to the registry, unless the file is open. The
+/// editor's buffer is analyzed instead until it is closed, its unsaved blocks are the ones to
+/// find.
+#[cfg(feature = "intelligence")]
+async fn analyze(ctx: &crate::Backend, uri: &lsp_types::Url, handle: &handler::Handle) {
+    if ctx.project.documents.get(uri).await.is_some() {
+        return;
+    }
+
+    if let Ok(blocks) = handle.blocks.read() {
+        ctx.analyze(uri.clone(), blocks.clone(), None);
+    }
+}
+
+/// Asks the client to report changes to the included files, so the index follows edits made
+/// outside of the editor. Nothing is registered when the client cannot register file watchers.
+pub async fn watch(ctx: &crate::Backend) -> anyhow::Result<()> {
+    let Some(capabilities) = ctx
+        .capabilities
+        .get()
+        .and_then(|capabilities| capabilities.workspace.as_ref())
+        .and_then(|workspace| workspace.did_change_watched_files.as_ref())
+        .filter(|watched| watched.dynamic_registration == Some(true))
+    else {
+        return Ok(());
+    };
+    let relative = capabilities.relative_pattern_support == Some(true);
+
+    let roots = ctx.project.roots.read().await.clone();
+    let include = ctx.config.read().await.workspace.include.clone();
+
+    let mut watchers = Vec::with_capacity(roots.len() * include.len());
+    for root in &roots {
+        // Without relative patterns the globs are anchored at the folder's path
+        let base = match relative {
+            true => None,
+            false => match root.to_file_path() {
+                Ok(path) => Some(path.to_string_lossy().replace('\\', "/")),
+                Err(_) => continue,
+            },
+        };
+
+        for pattern in &include {
+            let glob_pattern = match &base {
+                Some(base) => lsp_types::GlobPattern::String(format!(
+                    "{}/{}",
+                    base.trim_end_matches('/'),
+                    pattern
+                )),
+                None => lsp_types::GlobPattern::Relative(lsp_types::RelativePattern {
+                    base_uri: lsp_types::OneOf::Right(root.clone()),
+                    pattern: pattern.clone(),
+                }),
+            };
+            watchers.push(lsp_types::FileSystemWatcher {
+                glob_pattern,
+                kind: None,
+            });
+        }
+    }
+
+    let options = lsp_types::DidChangeWatchedFilesRegistrationOptions { watchers };
+    let registration = lsp_types::Registration {
+        id: "marky/watch".to_string(),
+        method: "workspace/didChangeWatchedFiles".to_string(),
+        register_options: Some(serde_json::to_value(options)?),
+    };
+    ctx.client.register_capability(vec![registration]).await?;
+
+    Ok(())
+}
+
+/// Reloads the included files changed on disk and drops the deleted ones from the index.
+pub async fn did_change_watched_files(
+    ctx: &crate::Backend,
+    params: lsp_types::DidChangeWatchedFilesParams,
+) -> anyhow::Result<()> {
+    let roots = ctx
+        .project
+        .roots
+        .read()
+        .await
+        .iter()
+        .filter_map(|root| root.to_file_path().ok())
+        .collect::<Vec<_>>();
+    let config = ctx.config.read().await.workspace.clone();
+    let include = glob_set(&config.include)?;
+    let exclude = glob_set(&config.exclude)?;
+
+    let mut failed = Vec::new();
+    for change in params.changes {
+        let Ok(path) = change.uri.to_file_path() else {
+            continue;
+        };
+        if !roots
+            .iter()
+            .any(|root| matches(root, &path, &include, &exclude))
+        {
+            continue;
+        }
+
+        if change.typ == lsp_types::FileChangeType::DELETED {
+            ctx.project.workspace.remove(&change.uri).await;
+        } else if let Err(e) = refresh(ctx, &change.uri).await {
+            failed.push(format!("{} ({})", change.uri, e));
+        }
+    }
+
+    #[cfg(feature = "intelligence")]
+    ctx.project
+        .registry
+        .garbage_collect(&ctx.project.hashes().await)?;
+
+    // Links into the changed files may have been fixed or broken
+    diagnostic::refresh(ctx).await?;
+
+    if !failed.is_empty() {
+        anyhow::bail!("Failed to reload {}", failed.join(", "));
+    }
+
+    Ok(())
+}
+
+async fn load(path: &Path) -> anyhow::Result<(lsp_types::Url, handler::Handle)> {
+    let uri = lsp_types::Url::from_file_path(path)
+        .map_err(|_| anyhow::anyhow!("Not an absolute path: {}", path.display()))?;
+    let text = tokio::fs::read_to_string(path).await?;
+
+    let mut parser = tree_sitter::Parser::new();
+    parser.set_language(tree_sitter_md::language())?;
+
+    let handle = handler::Handle::new(&text, &mut parser)?;
+
+    Ok((uri, handle))
+}
+
+fn discover(roots: &[PathBuf], config: &Config) -> anyhow::Result<Vec<PathBuf>> {
+    let include = glob_set(&config.include)?;
+    let exclude = glob_set(&config.exclude)?;
+
+    let mut paths = Vec::new();
+
+    for root in roots {
+        // `.gitignore` files should be honoured even outside of a git checkout
+        let walker = ignore::WalkBuilder::new(root).require_git(false).build();
+
+        for entry in walker.flatten() {
+            if !entry.file_type().is_some_and(|kind| kind.is_file()) {
+                continue;
+            }
+
+            let path = entry.path();
+            if matches(root, path, &include, &exclude) {
+                paths.push(path.to_path_buf());
+            }
+        }
+    }
+
+    Ok(paths)
+}
+
+/// Whether `path` is one of the files of `root` to index.
+fn matches(root: &Path, path: &Path, include: &GlobSet, exclude: &GlobSet) -> bool {
+    path.strip_prefix(root)
+        .is_ok_and(|relative| include.is_match(relative) && !exclude.is_match(relative))
+}
+
+fn glob_set(patterns: &[String]) -> anyhow::Result<GlobSet> {
+    let mut builder = GlobSetBuilder::new();
+    for pattern in patterns {
+        builder.add(Glob::new(pattern)?);
+    }
+
+    Ok(builder.build()?)
+}